@@ -1,252 +1,2612 @@
+use crate::audio::crossfade::CrossfadeCurve;
+use crate::audio::effects::CompressorPreset;
+use crate::audio::export::{ExportFormat, ExportJob};
 use crate::audio::player::AudioPlayer;
-use crate::audio::waveform::WaveformGenerator;
-use crate::utils::file_scanner::AudioFileScanner;
-use eframe::egui::{self, Color32, Context, CentralPanel, Pos2, ScrollArea, SidePanel, Stroke, Vec2, Layout, Rect};
+use crate::audio::spectrum::{Spectrogram, SpectrumAnalyzer, ANALYZER_SIZE, HOP_SIZE};
+use crate::audio::waveform::{EnvelopeMode, WaveformGenerator, WaveformScale, HISTOGRAM_BIN_DB};
+use crate::audio::waveform_cache;
+use crate::settings::{ClickAction, Settings};
+use crate::ui::level_histogram::LevelHistogram;
+use crate::ui::spectrogram_view::spectrogram_image;
+use crate::ui::waveform_visualizer::{
+    genre_color, loudness_color, WaveformChannelMode, WaveformColorMode, WaveformOverview, WaveformStyle,
+    WaveformView, WaveformVisualizer,
+};
+use crate::utils::file_scanner::{AudioFileScanner, DirectoryWatcher, SortKey};
+use crate::utils::metadata::{probe_duration, read_metadata, TrackMetadata};
+use crate::utils::playlist::{load_playlist, PlaylistLocation};
+use crate::utils::time_format::{format_duration, format_duration_precise};
+use crate::utils::trash::move_to_trash;
+use eframe::egui::{self, Color32, Context, CentralPanel, ScrollArea, SidePanel, Stroke, Vec2, Layout, Rect};
 use eframe::Frame;
-use std::time::Duration;
-use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
-use std::fs::File;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
 
 
 const ACCENT_COLOR: Color32 = Color32::from_rgb(0x03, 0x45, 0xfc);
 const LIGHTER_ACCENT_COLOR: Color32 = Color32::from_rgb(0x66, 0x99, 0xFF);
+const WAVEFORM_COLOR: Color32 = Color32::LIGHT_BLUE;
+const BOOKMARK_COLOR: Color32 = Color32::from_rgb(0xFF, 0xC8, 0x3D);
+
+// What happens when the playing track ends
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RepeatMode {
+    Off,
+    // Advance through the list, wrapping back to the first track
+    All,
+    One,
+}
+
+impl RepeatMode {
+    // The mode the repeat button switches to next
+    fn next(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Repeat off",
+            Self::All => "Repeat all",
+            Self::One => "Repeat current track",
+        }
+    }
+}
+
+// How a modified click changes the list selection
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SelectionChange {
+    Only,
+    Toggle,
+    Extend,
+}
+
+// Actions applied to every file in a multi-selection
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BatchAction {
+    Queue,
+    Export,
+    Trash,
+}
+
+// How long the current row stays flashed after jumping to it
+const FLASH_DURATION: Duration = Duration::from_millis(800);
+
+// Length of audio shown in the scrolling waveform view
+const WAVEFORM_WINDOW_SECS: f32 = 2.0;
+
+// Edge length of the cover art thumbnail in the track header
+const COVER_ART_SIZE: f32 = 40.0;
+
+// Height of the full-track overview strip above the scrolling waveform
+const OVERVIEW_HEIGHT: f32 = 36.0;
+
+// Window of audio around the playhead used for the phase correlation meter
+const CORRELATION_WINDOW_SECS: f32 = 0.1;
+
+// Level treated as clipping by the clip LED, and how long the LED stays lit after a clip
+const CLIP_THRESHOLD: f32 = 0.999;
+const CLIP_HOLD: Duration = Duration::from_secs(1);
+
+// Window of audio the level meters measure, how fast their peak falls and the level at their bottom
+const METER_WINDOW_SECS: f32 = 0.05;
+const METER_FALL_DB_PER_SEC: f32 = 24.0;
+const METER_FLOOR_DB: f32 = -60.0;
+
+// File names listed in the confirmation before trashing several files
+const TRASH_CONFIRM_NAMES: usize = 10;
+
+// Length of a click-to-preview snippet
+const PREVIEW_DURATION: Duration = Duration::from_secs(5);
+
+// Minimum shortfall against the probed duration before an ending counts as a decode failure
+const EARLY_END_TOLERANCE: Duration = Duration::from_secs(2);
+
+// How many folder levels below the chosen directory are scanned
+const SCAN_DEPTH: usize = 3;
+
+// Extensions opened as playlists from the dialog or a drop
+const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+// How often listed files are re-checked for existence on disk
+const MISSING_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Playback rates offered in the control bar
+const SPEED_PRESETS: [f32; 5] = [0.75, 1.0, 1.25, 1.5, 2.0];
+
+// Positions closer than this to either end of a track aren't worth resuming
+const RESUME_MARGIN: Duration = Duration::from_secs(30);
+
+// How often the playing track's position is saved for resuming
+const POSITION_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+// How close to the end of a track the next one is appended for a gapless transition
+const GAPLESS_LEAD: Duration = Duration::from_secs(2);
+
+// Distance the arrow keys seek
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+// Past this point Previous restarts the current track instead of going back one
+const PREVIOUS_RESTART_THRESHOLD: Duration = Duration::from_secs(3);
 
 
 pub struct AudioPlayerApp {
     audio_files: Vec<String>,
+    // Display titles for list entries that came from a playlist
+    entry_titles: HashMap<String, String>,
+    scan_receiver: Option<Receiver<String>>,
+    // Rescans the directory when files are added or removed; None while showing a playlist
+    directory_watcher: Option<DirectoryWatcher>,
+    // Lengths of listed files keyed by path, None where the file doesn't declare one. Filled in
+    // by a background worker for the summary under the list heading.
+    list_durations: HashMap<String, Option<Duration>>,
+    list_duration_receiver: Option<Receiver<(String, Option<Duration>)>>,
+    // Case-insensitive file name filter for the list; only affects what's shown
+    search_query: String,
     directory: Option<String>,
     player: AudioPlayer,
     waveform: WaveformGenerator,
     total_duration: Duration,
+    // Whether total_duration came from the file; it stays zero when the length can't be read
+    duration_known: bool,
+    // Output devices listed in the settings, refreshed whenever the settings window opens
+    output_devices: Vec<String>,
+    // Saved position offered for the file that just started, when not resuming automatically
+    resume_offer: Option<(String, Duration)>,
+    position_saved_at: Option<Instant>,
+    // Most recent failure to play or load a file, shown as a dismissible banner
+    last_error: Option<String>,
+    // Tags of the playing file, read on a worker like the duration
+    track_metadata: TrackMetadata,
+    metadata_receiver: Option<Receiver<TrackMetadata>>,
+    // Uploaded cover art and the file it belongs to, kept until a different file plays
+    cover_texture: Option<(String, egui::TextureHandle)>,
+    // Spectrum of the current track for the spectrogram view, filled in while that view is shown
+    spectrogram: Spectrogram,
+    spectrogram_texture: Option<egui::TextureHandle>,
+    // Live band levels of the audio around the playhead
+    spectrum_analyzer: SpectrumAnalyzer,
+    // Displayed left/right peak levels in dBFS, falling gradually, and when each last clipped
+    meter_levels: [f32; 2],
+    meter_clipped_at: [Option<Instant>; 2],
+    // Modification time and size of the playing file when playback started
+    playing_file_stamp: Option<(SystemTime, u64)>,
+    // Set when a track stops decoding partway through; holds the file and where it stopped
+    playback_error: Option<(String, Duration)>,
+    settings: Settings,
+    show_settings: bool,
+    show_histogram: bool,
+    scroll_to_current: bool,
+    flash_started: Option<Instant>,
+    follow_playhead: bool,
+    // Center of the waveform view in seconds while free scrolling
+    waveform_offset: f32,
+    // Overview peaks with the buffer length, bucket count and generating state they were computed
+    // for, so the reduction only reruns when new audio arrives, the strip is resized or the decode
+    // finishes and the cached overview gives way to the buffer's own
+    overview_cache: (usize, usize, Vec<(f32, f32)>, bool),
+    export_job: Option<ExportJob>,
+    // Exports waiting to start once the current job finishes
+    pending_exports: Vec<(String, PathBuf)>,
+    // Smoothed L/R correlation shown by the phase meter
+    phase_correlation: f32,
+    // Snippet being previewed from the file list, and when its audible part began. The start is
+    // None while the track is still being analysed for its loudest section.
+    preview: Option<(String, Option<Instant>)>,
+    // When the clip LED last saw a clipped sample
+    clip_detected_at: Option<Instant>,
+    // Focused file in the list, also the anchor for Shift-click range selection
+    selected_file: Option<String>,
+    // All files in the current multi-selection, including the focused one
+    selected_files: HashSet<String>,
+    idle_since: Option<Instant>,
+    repeat_mode: RepeatMode,
+    // Pick the next track at random; played_indices tracks which list entries shuffle has
+    // already visited so every track plays once before any repeats
+    shuffle: bool,
+    played_indices: HashSet<usize>,
+    // Number of times repeat-one plays a track before advancing; 0 repeats forever
+    loop_count: u32,
+    loops_remaining: u32,
+    // Tracks to play after the current one, in order
+    queue: Vec<String>,
+    // Range dragged out on the waveform; loops during playback and limits exports
+    selection: Option<(Duration, Duration)>,
+    selection_anchor: Option<Duration>,
+    // Loop start set by Shift-clicking the waveform, until a second Shift-click sets the end
+    loop_point_a: Option<Duration>,
+    // Tracks loaded for A/B comparison and the slot currently playing
+    ab_slots: [Option<String>; 2],
+    ab_active: usize,
+    // Measured level of each slot in dBFS, used to volume-match the comparison
+    ab_loudness: [Option<f32>; 2],
+    // Listed files that no longer exist on disk, refreshed periodically rather than every frame
+    missing_files: HashSet<String>,
+    missing_checked: Option<Instant>,
+    // Edit buffer for the ignored folder list in the settings window
+    ignored_folders_text: String,
+    audio_extensions_text: String,
 }
 
 impl Default for AudioPlayerApp {
     fn default() -> Self {
-        let mut app = Self {
+        Self {
             audio_files: Vec::new(),
+            entry_titles: HashMap::new(),
+            scan_receiver: None,
+            directory_watcher: None,
+            list_durations: HashMap::new(),
+            list_duration_receiver: None,
+            search_query: String::new(),
             directory: dirs::audio_dir().map(|p| p.to_string_lossy().to_string()),
             player: AudioPlayer::default(),
             waveform: WaveformGenerator::default(),
             total_duration: Duration::ZERO,
-        };
-
-        app.scan_audio_files(); // Scan files immediately on startup
-        app
+            output_devices: Vec::new(),
+            resume_offer: None,
+            position_saved_at: None,
+            last_error: None,
+            track_metadata: TrackMetadata::default(),
+            metadata_receiver: None,
+            cover_texture: None,
+            spectrogram: Spectrogram::default(),
+            spectrogram_texture: None,
+            spectrum_analyzer: SpectrumAnalyzer::default(),
+            meter_levels: [METER_FLOOR_DB; 2],
+            meter_clipped_at: [None; 2],
+            duration_known: false,
+            playing_file_stamp: None,
+            playback_error: None,
+            settings: Settings::default(),
+            show_settings: false,
+            show_histogram: false,
+            scroll_to_current: false,
+            flash_started: None,
+            follow_playhead: true,
+            waveform_offset: 0.0,
+            overview_cache: (0, 0, Vec::new(), false),
+            export_job: None,
+            pending_exports: Vec::new(),
+            phase_correlation: 1.0,
+            clip_detected_at: None,
+            preview: None,
+            selected_file: None,
+            selected_files: HashSet::new(),
+            idle_since: None,
+            repeat_mode: RepeatMode::Off,
+            shuffle: false,
+            played_indices: HashSet::new(),
+            loop_count: 0,
+            loops_remaining: 0,
+            queue: Vec::new(),
+            selection: None,
+            loop_point_a: None,
+            selection_anchor: None,
+            ab_slots: [None, None],
+            ab_active: 0,
+            ab_loudness: [None, None],
+            missing_files: HashSet::new(),
+            missing_checked: None,
+            ignored_folders_text: String::new(),
+            audio_extensions_text: String::new(),
+        }
     }
 }
 
 
 impl eframe::App for AudioPlayerApp {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        ctx.request_repaint_after(Duration::from_millis(30));
+        if self.is_animating() {
+            let fps = self.settings.target_fps.max(1);
+            ctx.request_repaint_after(Duration::from_secs_f32(1.0 / fps as f32));
+        } else if self.idle_since.is_some() {
+            // Keep the idle-release timer ticking without redrawing at full rate
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+        self.track_window_geometry(ctx);
         self.waveform.update_buffer();
+        if let Some(device) = self.player.take_device_fallback() {
+            self.last_error = Some(format!("Output device \"{}\" is not available, using the default device", device));
+        }
+        if let Some(err) = self.waveform.take_error() {
+            self.last_error = Some(format!("Could not load the waveform: {}", err));
+        }
+        if !self.duration_known
+            && let Some(duration) = self.waveform.duration()
+        {
+            self.total_duration = duration;
+            self.duration_known = true;
+        }
+        if self.scan_receiver.is_none() && self.directory_watcher.as_ref().is_some_and(DirectoryWatcher::poll_changed) {
+            self.scan_audio_files();
+        }
+        self.poll_scan();
+        self.poll_metadata(ctx);
+        self.poll_list_durations();
+        self.refresh_missing_files();
+        if let Some(job) = &mut self.export_job {
+            job.poll();
+        }
+        self.start_pending_export();
+        self.handle_shortcuts(ctx);
+        self.handle_dropped_files(ctx);
+        self.update_preview();
+        self.save_position_periodically();
+        self.prepare_gapless_transition();
+        self.follow_gapless_transition();
+        self.handle_track_finished();
+        self.update_ab_levels();
+        self.loop_selection();
+        self.release_idle_output();
 
         self.render_ui(ctx);
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.remember_position();
+        self.settings.last_directory = self.directory.clone();
+        self.settings.last_file = self.player.current_file().map(ToOwned::to_owned);
+        self.settings.last_position = self.player.progress();
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
 }
 
 impl AudioPlayerApp {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::default();
+        if let Some(storage) = cc.storage {
+            app.settings = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        }
+        app.player.set_volume(app.settings.volume);
+        app.player.set_speed(app.settings.playback_speed);
+        // Nothing is open yet, so this only records the choice
+        let _ = app.player.set_output_device(app.settings.output_device.clone());
+        app.apply_compressor_settings();
+        app.apply_crossfade_settings();
+        app.apply_waveform_memory_cap();
+        app.apply_waveform_chunk_size();
+        app.apply_silence_threshold();
+        app.waveform.set_envelope_mode(app.settings.waveform_envelope);
+        app.waveform.set_scale(app.settings.waveform_scale);
+        app.ignored_folders_text = app.settings.ignored_folders.join(", ");
+        app.audio_extensions_text = app.settings.audio_extensions.join(", ");
+        // Reopen the last browsed folder, staying with the audio dir if it has since gone away
+        if let Some(dir) = app.settings.last_directory.clone().filter(|dir| Path::new(dir).is_dir()) {
+            app.directory = Some(dir);
+        }
+        if app.settings.resume_on_launch {
+            app.resume_last_session();
+        } else {
+            app.scan_audio_files(); // Start scanning in the background so the first frame isn't blocked
+        }
+        app
+    }
+
+    // Reopens the last folder and track from the previous run, skipping a track that has since
+    // been moved or deleted
+    fn resume_last_session(&mut self) {
+        self.scan_audio_files();
+
+        let Some(file) = self.settings.last_file.clone() else {
+            return;
+        };
+        if !Path::new(&file).exists() {
+            eprintln!("Last played file no longer exists: {}", file);
+            return;
+        }
+
+        self.play_file(&file);
+        self.resume_offer = None;
+        self.selected_file = Some(file);
+        self.scroll_to_current = true;
+        if let Err(err) = self.player.seek(self.settings.last_position) {
+            self.last_error = Some(format!("Could not restore the playback position: {}", err));
+        }
+    }
+
+    // Keeps the settings in step with the window so the size and maximized state are saved
+    fn track_window_geometry(&mut self, ctx: &Context) {
+        ctx.input(|i| {
+            let viewport = i.viewport();
+            if let Some(maximized) = viewport.maximized {
+                self.settings.window_maximized = maximized;
+            }
+            // The maximized size isn't useful to restore; keep the last normal size instead
+            if !self.settings.window_maximized
+                && viewport.fullscreen != Some(true)
+                && let Some(rect) = viewport.inner_rect
+            {
+                self.settings.window_size = [rect.width(), rect.height()];
+            }
+        });
+    }
+
     fn render_ui(&mut self, ctx: &Context) {
         self.render_sidebar(ctx);
         self.render_main_panel(ctx);
+        self.render_settings_window(ctx);
+        self.render_histogram_window(ctx);
+        self.render_drop_overlay(ctx);
     }
 
-    fn render_sidebar(&mut self, ctx: &Context) {
-        SidePanel::left("side_panel").default_width(300.0).show(ctx, |ui| {
+    // Dims the window while files are dragged over it
+    fn render_drop_overlay(&self, ctx: &Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+            return;
+        }
 
-            ui.add_space(10.0);
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+        let rect = ctx.screen_rect();
+        painter.rect_filled(rect, 0.0, Color32::from_black_alpha(180));
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop audio here",
+            egui::FontId::proportional(24.0),
+            Color32::WHITE,
+        );
+    }
 
-            ui.horizontal(|ui| {
-                ui.with_layout(Layout::left_to_right(egui::Align::Center).with_main_justify(true), |ui| {
-                    ui.colored_label(Color32::WHITE, egui::RichText::new("Select file to play").heading());
-                });
+    // Opens a dropped folder or playlist, or adds dropped audio files to the list. A single
+    // dropped file also starts playing.
+    fn handle_dropped_files(&mut self, ctx: &Context) {
+        let dropped: Vec<PathBuf> =
+            ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        if dropped.is_empty() {
+            return;
+        }
+
+        if let Some(dir) = dropped.iter().find(|path| path.is_dir()) {
+            self.directory = Some(dir.display().to_string());
+            self.scan_audio_files();
+            return;
+        }
+        let playlist_extensions = PLAYLIST_EXTENSIONS.map(String::from);
+        if let Some(playlist) = dropped.iter().find(|path| AudioFileScanner::is_supported(path, &playlist_extensions)) {
+            self.open_playlist(playlist);
+            return;
+        }
+
+        let files: Vec<String> = dropped
+            .iter()
+            .filter(|path| AudioFileScanner::is_supported(path, &self.settings.audio_extensions))
+            .map(|path| path.display().to_string())
+            .collect();
+        if files.is_empty() {
+            self.last_error = Some("None of the dropped files are supported audio files".to_string());
+            return;
+        }
+
+        for file in &files {
+            if !self.audio_files.contains(file) {
+                self.audio_files.push(file.clone());
+            }
+        }
+        self.played_indices.clear();
+        self.missing_checked = None;
+        if let [file] = files.as_slice() {
+            self.play_file(file);
+        }
+    }
+
+    fn render_histogram_window(&mut self, ctx: &Context) {
+        let mut open = self.show_histogram;
+
+        egui::Window::new("Level distribution")
+            .open(&mut open)
+            .default_size(Vec2::new(320.0, 160.0))
+            .show(ctx, |ui| {
+                match self.waveform.get_histogram() {
+                    Some(bins) => {
+                        let (rect, _) = ui.allocate_exact_size(
+                            Vec2::new(ui.available_width().max(200.0), 140.0),
+                            egui::Sense::hover(),
+                        );
+                        LevelHistogram::new(bins, HISTOGRAM_BIN_DB)
+                            .with_color(self.waveform_color())
+                            .paint(ui.painter(), rect);
+                        ui.label(egui::RichText::new("Sample level (dBFS)").weak().small());
+                    }
+                    None if self.player.current_file().is_some() => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Analysing…");
+                        });
+                    }
+                    None => {
+                        ui.label("Play a track to see its level distribution");
+                    }
+                }
             });
 
-            ui.add_space(15.0);
+        self.show_histogram = open;
+    }
 
-            ui.horizontal(|ui| {
-                let available_width = ui.available_width();
-                let button_size = egui::vec2(100.0, 20.0);
+    fn render_output_device_picker(&mut self, ui: &mut egui::Ui) {
+        let before = self.settings.output_device.clone();
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Output device")
+                .selected_text(self.settings.output_device.as_deref().unwrap_or("System default"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.settings.output_device, None, "System default");
+                    for device in &self.output_devices {
+                        ui.selectable_value(&mut self.settings.output_device, Some(device.clone()), device);
+                    }
+                });
+            let refresh_response = ui.small_button("⟳").on_hover_text("Refresh device list");
+            Self::accessible_label(&refresh_response, egui::WidgetType::Button, "Refresh device list");
+            if refresh_response.clicked() {
+                self.output_devices = AudioPlayer::output_devices();
+            }
+        });
 
-                let indent = (available_width) / 2.0 - button_size.x / 3.0;
-                ui.add_space(indent);
+        if self.settings.output_device != before
+            && let Err(err) = self.player.set_output_device(self.settings.output_device.clone())
+        {
+            self.last_error = Some(format!("Could not switch output device: {}", err));
+        }
+    }
 
-                let button_response = ui.scope(|ui| {
-                    ui.spacing_mut().button_padding = Vec2::new(14.0, 8.0);
+    fn render_settings_window(&mut self, ctx: &Context) {
+        let mut open = self.show_settings;
+
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.render_output_device_picker(ui);
+
+                let mut changed = ui
+                    .checkbox(&mut self.settings.night_mode, "Night mode (tame loud peaks)")
+                    .changed();
+
+                ui.add_enabled_ui(self.settings.night_mode, |ui| {
+                    egui::ComboBox::from_label("Compressor preset")
+                        .selected_text(self.settings.compressor_preset.label())
+                        .show_ui(ui, |ui| {
+                            for preset in CompressorPreset::ALL {
+                                changed |= ui
+                                    .selectable_value(&mut self.settings.compressor_preset, preset, preset.label())
+                                    .changed();
+                            }
+                        });
+                });
 
-                    ui.add(
-                        egui::Button::new(
-                            egui::RichText::new("Browse")
-                                .color(Color32::WHITE)
-                                .size(14.0),
-                        )
-                            .fill(ACCENT_COLOR)
-                            .rounding(egui::Rounding::same(4)),
-                    )
+                if changed {
+                    self.apply_compressor_settings();
+                }
+
+                if ui
+                    .checkbox(&mut self.settings.replay_gain, "ReplayGain normalization")
+                    .on_hover_text("Play tagged tracks at a similar loudness")
+                    .changed()
+                {
+                    self.apply_replay_gain();
+                }
 
+                ui.separator();
+
+                let mut crossfade_changed = ui
+                    .add(egui::Slider::new(&mut self.settings.crossfade_secs, 0.0..=10.0).text("Crossfade (s)"))
+                    .changed();
+                ui.add_enabled_ui(self.settings.crossfade_secs > 0.0, |ui| {
+                    egui::ComboBox::from_label("Crossfade curve")
+                        .selected_text(self.settings.crossfade_curve.label())
+                        .show_ui(ui, |ui| {
+                            for curve in CrossfadeCurve::ALL {
+                                crossfade_changed |= ui
+                                    .selectable_value(&mut self.settings.crossfade_curve, curve, curve.label())
+                                    .changed();
+                            }
+                        });
                 });
+                ui.add_enabled(
+                    self.settings.crossfade_secs == 0.0,
+                    egui::Checkbox::new(&mut self.settings.gapless, "Gapless playback"),
+                )
+                .on_hover_text("Start the next track with no pause between them")
+                .on_disabled_hover_text("Crossfading takes over track transitions");
+                crossfade_changed |= ui
+                    .add(egui::Slider::new(&mut self.settings.fade_ms, 0..=1000).suffix(" ms").text("Start/stop fade"))
+                    .on_hover_text("0 starts and stops tracks abruptly")
+                    .changed();
+                if crossfade_changed {
+                    self.apply_crossfade_settings();
+                }
 
-                if button_response.inner.clicked() {
-                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
-                        self.directory = Some(dir.display().to_string());
-                        self.scan_audio_files();
-                    }
+                ui.add(egui::Slider::new(&mut self.settings.target_fps, 10..=144).text("Frame rate while playing"));
+                ui.checkbox(&mut self.settings.vsync, "VSync")
+                    .on_hover_text("Takes effect after restarting the app");
+
+                ui.horizontal(|ui| {
+                    ui.label("Release audio device after idle");
+                    ui.add(egui::DragValue::new(&mut self.settings.idle_release_secs).range(0..=3600).suffix(" s"));
+                })
+                .response
+                .on_hover_text("0 keeps the device open");
+
+                egui::ComboBox::from_label("Waveform style")
+                    .selected_text(self.settings.waveform_style.label())
+                    .show_ui(ui, |ui| {
+                        for style in WaveformStyle::ALL {
+                            ui.selectable_value(&mut self.settings.waveform_style, style, style.label());
+                        }
+                    });
+
+                ui.checkbox(&mut self.settings.rectified_waveform, "Rectified waveform (absolute value)");
+                ui.checkbox(&mut self.settings.normalize_waveform, "Normalize waveform to the track's peak")
+                    .on_hover_text("Quietly mastered tracks fill the height too; turn off to compare absolute levels");
+
+                let envelope_before = self.settings.waveform_envelope;
+                egui::ComboBox::from_label("Waveform envelope")
+                    .selected_text(self.settings.waveform_envelope.label())
+                    .show_ui(ui, |ui| {
+                        for mode in EnvelopeMode::ALL {
+                            ui.selectable_value(&mut self.settings.waveform_envelope, mode, mode.label());
+                        }
+                    });
+                if self.settings.waveform_envelope != envelope_before {
+                    self.waveform.set_envelope_mode(self.settings.waveform_envelope);
                 }
-            });
 
-            ui.add_space(10.0);
+                let scale_before = self.settings.waveform_scale;
+                egui::ComboBox::from_label("Waveform scale")
+                    .selected_text(self.settings.waveform_scale.label())
+                    .show_ui(ui, |ui| {
+                        for scale in WaveformScale::ALL {
+                            ui.selectable_value(&mut self.settings.waveform_scale, scale, scale.label());
+                        }
+                    });
+                if self.settings.waveform_scale != scale_before {
+                    self.waveform.set_scale(self.settings.waveform_scale);
+                }
 
-            ui.separator();
+                egui::ComboBox::from_label("Waveform channels")
+                    .selected_text(self.settings.waveform_channel_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in WaveformChannelMode::ALL {
+                            ui.selectable_value(&mut self.settings.waveform_channel_mode, mode, mode.label());
+                        }
+                    });
 
-            let mut file_to_play: Option<String> = None;
+                egui::ComboBox::from_label("Waveform color")
+                    .selected_text(self.settings.waveform_color_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in WaveformColorMode::ALL {
+                            ui.selectable_value(&mut self.settings.waveform_color_mode, mode, mode.label());
+                        }
+                    });
 
-            egui::Frame::default()
-                .inner_margin(egui::Margin::same(8))
-                .show(ui, |ui| {
-                    ScrollArea::vertical().show(ui, |ui| {
-                        for file in &self.audio_files {
-                            let file_name = Path::new(file)
-                                .file_name()
-                                .unwrap_or_default()
-                                .to_string_lossy()
-                                .to_string();
+                let memory_response = ui
+                    .add(
+                        egui::Slider::new(&mut self.settings.waveform_memory_mb, 16..=2048)
+                            .logarithmic(true)
+                            .suffix(" MB")
+                            .text("Waveform memory cap"),
+                    )
+                    .on_hover_text("Very long files are shown at a lower resolution to stay under this limit");
+                if memory_response.changed() {
+                    self.apply_waveform_memory_cap();
+                }
 
-                            let is_current = self.player.current_file()
-                                .map_or(false, |current| current == file);
+                if ui
+                    .button("Clear waveform cache")
+                    .on_hover_text("Delete the saved track overviews; they are rebuilt the next time each track plays")
+                    .clicked()
+                    && let Err(err) = waveform_cache::clear()
+                {
+                    self.last_error = Some(format!("Could not clear the waveform cache: {}", err));
+                }
 
-                            if ui.selectable_label(is_current, &file_name).clicked() {
-                                file_to_play = Some(file.clone());
-                            }
+                let silence_response = ui
+                    .add(
+                        egui::Slider::new(&mut self.settings.silence_threshold_db, -80.0..=-20.0)
+                            .suffix(" dB")
+                            .text("Silence threshold"),
+                    )
+                    .on_hover_text("Audio quieter than this counts as a gap for gap navigation");
+                if silence_response.changed() {
+                    self.apply_silence_threshold();
+                }
+
+                let chunk_response = ui
+                    .add(egui::Slider::new(&mut self.settings.waveform_chunk_ms, 0..=1000).suffix(" ms").text("Waveform chunk size"))
+                    .on_hover_text("Larger chunks use less CPU while the waveform loads but fill it in less smoothly");
+                if chunk_response.changed() {
+                    self.apply_waveform_chunk_size();
+                }
+
+                ui.separator();
+
+                let mut filter_changed = ui
+                    .checkbox(&mut self.settings.skip_hidden_files, "Skip hidden files and folders")
+                    .changed();
+                filter_changed |= ui
+                    .checkbox(&mut self.settings.follow_symlinks, "Follow symlinked folders")
+                    .on_hover_text("Also list files inside folders that are symbolic links")
+                    .changed();
+                // The list is applied once editing finishes so each keystroke doesn't trigger a rescan
+                let text_focused = ui
+                    .horizontal(|ui| {
+                        ui.label("Ignored folders");
+                        ui.add(egui::TextEdit::singleline(&mut self.ignored_folders_text).hint_text(".Trash, @eaDir"))
+                            .on_hover_text("Comma-separated folder names that scans won't look inside")
+                            .has_focus()
+                    })
+                    .inner;
+                let ignored_folders: Vec<String> = self
+                    .ignored_folders_text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect();
+                if ignored_folders != self.settings.ignored_folders && !text_focused {
+                    self.settings.ignored_folders = ignored_folders;
+                    filter_changed = true;
+                }
+
+                let text_focused = ui
+                    .horizontal(|ui| {
+                        ui.label("File extensions");
+                        ui.add(egui::TextEdit::singleline(&mut self.audio_extensions_text).hint_text("mp3, flac, opus"))
+                            .on_hover_text("Comma-separated extensions of the files to list")
+                            .has_focus()
+                    })
+                    .inner;
+                let audio_extensions: Vec<String> = self
+                    .audio_extensions_text
+                    .split(',')
+                    .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+                    .filter(|extension| !extension.is_empty())
+                    .collect();
+                if audio_extensions != self.settings.audio_extensions && !text_focused {
+                    self.settings.audio_extensions = audio_extensions;
+                    filter_changed = true;
+                }
+                if filter_changed {
+                    self.scan_audio_files();
+                }
+
+                ui.checkbox(&mut self.settings.resume_on_launch, "Resume last track on launch");
+                ui.checkbox(&mut self.settings.precise_time_display, "Show milliseconds in the time display");
+
+                egui::ComboBox::from_label("File list click")
+                    .selected_text(self.settings.click_action.label())
+                    .show_ui(ui, |ui| {
+                        for action in ClickAction::ALL {
+                            ui.selectable_value(&mut self.settings.click_action, action, action.label());
                         }
                     });
-                });
-            if let Some(file) = file_to_play {
-                self.play_file(&file);
+            });
+
+        self.show_settings = open;
+    }
+
+    fn render_volume_slider(&mut self, ui: &mut egui::Ui) {
+        let mut volume = self.player.volume();
+        let response = ui.add(egui::Slider::new(&mut volume, 0.0..=1.5).show_value(false).text("Volume"));
+        if response.changed() {
+            self.player.set_volume(volume);
+            self.settings.volume = volume;
+        }
+        response.on_hover_text(format!("{:.0}%", volume * 100.0));
+    }
+
+    fn render_pitch_control(&mut self, ui: &mut egui::Ui) {
+        let mut semitones = self.player.pitch_semitones();
+        let response = ui
+            .add(
+                egui::DragValue::new(&mut semitones)
+                    .range(-12..=12)
+                    .prefix("Pitch ")
+                    .suffix(" st"),
+            )
+            .on_hover_text("Transpose in semitones without changing tempo");
+        if response.changed() {
+            self.player.set_pitch_semitones(semitones);
+        }
+    }
+
+    fn render_speed_controls(&mut self, ui: &mut egui::Ui) {
+        for speed in SPEED_PRESETS {
+            let label = format!("{}×", speed);
+            let response = ui
+                .selectable_label(self.player.speed() == speed, &label)
+                .on_hover_text("Playback speed (also shifts pitch)");
+            Self::accessible_label(&response, egui::WidgetType::Button, &format!("Speed {}", label));
+            if response.clicked() {
+                self.player.set_speed(speed);
+                self.settings.playback_speed = speed;
             }
-        });
+        }
     }
 
-    pub fn render_main_panel(&mut self, ctx: &Context) {
-        CentralPanel::default().show(ctx, |ui| {
-            let available_width = ui.available_width();
-            let available_height = ui.available_height();
+    fn render_repeat_controls(&mut self, ui: &mut egui::Ui) {
+        let icon = if self.repeat_mode == RepeatMode::One { "🔂" } else { "🔁" };
+        let repeat_response = ui
+            .selectable_label(self.repeat_mode != RepeatMode::Off, icon)
+            .on_hover_text(format!("{} (click to change)", self.repeat_mode.label()));
+        Self::accessible_label(&repeat_response, egui::WidgetType::Button, self.repeat_mode.label());
+        if repeat_response.clicked() {
+            self.repeat_mode = self.repeat_mode.next();
+            self.loops_remaining = self.loop_count.saturating_sub(1);
+        }
 
-            let waveform_height = available_height * 0.5;
-            let play_bar_height = available_height * 0.2;
+        if self.repeat_mode == RepeatMode::One {
+            let response = ui
+                .add(
+                    egui::DragValue::new(&mut self.loop_count)
+                        .range(0..=99)
+                        .custom_formatter(|n, _| if n == 0.0 { "∞".to_string() } else { format!("{}×", n) }),
+                )
+                .on_hover_text("Times to play the track before advancing (0 = forever)");
+            if response.changed() {
+                self.loops_remaining = self.loop_count.saturating_sub(1);
+            }
+        }
+    }
 
-            ui.with_layout(Layout::top_down(egui::Align::Min), |ui| {
+    // Replays or advances when the current track reaches its end, according to the repeat mode
+    fn handle_track_finished(&mut self) {
+        if !self.player.is_finished() {
+            return;
+        }
+        let Some(file) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
 
-                ui.allocate_ui(Vec2::new(available_width, waveform_height), |ui| {
-                    self.render_waveform(ui);
-                });
+        // rodio ends a source quietly when decoding fails, so a track that stops early or whose
+        // file changed underneath it is reported instead of being treated as finished
+        let stopped_at = self.player.progress();
+        // Durations of some VBR files are estimates, so allow a margin proportional to the length
+        let tolerance = EARLY_END_TOLERANCE.max(self.total_duration / 50);
+        let ended_early = self.duration_known && stopped_at + tolerance < self.total_duration;
+        if ended_early || Self::file_stamp(&file) != self.playing_file_stamp {
+            self.player.stop();
+            self.playback_error = Some((file, stopped_at));
+            return;
+        }
 
-                ui.add_space(10.0);
+        match self.upcoming_track(&file) {
+            // Looping keeps the waveform and analysis of the current file
+            Some(next) if next == file => {
+                if let Err(err) = self.player.play(&file) {
+                    self.last_error = Some(format!("Could not play {}: {}", Self::display_name(&file), err));
+                }
+            }
+            Some(next) => self.play_file(&next),
+            None if self.repeat_mode == RepeatMode::One => self.player.stop(),
+            None => {}
+        }
+    }
 
-                ui.allocate_ui(Vec2::new(available_width, play_bar_height), |ui| {
-                    let progress_secs = self.player.progress().as_secs();
-                    let total_secs = self.total_duration.as_secs();
+    // Picks what plays after `file` ends according to the repeat mode, consuming a loop or queue
+    // entry as it does
+    fn upcoming_track(&mut self, file: &str) -> Option<String> {
+        // Files deleted since the scan are dropped from the list and passed over
+        for _ in 0..=self.audio_files.len() + self.queue.len() {
+            let next = self.upcoming_candidate(file)?;
+            if Path::new(&next).exists() {
+                return Some(next);
+            }
+            self.forget_file(&next);
+        }
+        None
+    }
 
-                    let ratio = if total_secs > 0 {
-                        (progress_secs as f32 / total_secs as f32).clamp(0.0, 1.0)
-                    } else {
-                        0.0
-                    };
+    fn upcoming_candidate(&mut self, file: &str) -> Option<String> {
+        match self.repeat_mode {
+            RepeatMode::One if self.loop_count == 0 || self.loops_remaining > 0 => {
+                self.loops_remaining = self.loops_remaining.saturating_sub(1);
+                Some(file.to_string())
+            }
+            RepeatMode::One => self.take_queued().or_else(|| self.next_track(file)),
+            RepeatMode::All => self
+                .take_queued()
+                .or_else(|| self.next_track(file))
+                .or_else(|| self.audio_files.first().cloned()),
+            RepeatMode::Off => self.take_queued(),
+        }
+    }
 
-                    let bar_height = 6.0;
-                    let horizontal_padding = 12.0;
+    // Appends the upcoming track to the player shortly before the current one ends so it
+    // follows without a gap. Crossfading handles its own transitions instead.
+    fn prepare_gapless_transition(&mut self) {
+        if !self.settings.gapless
+            || self.settings.crossfade_secs > 0.0
+            || !self.duration_known
+            || !self.player.is_playing()
+            || self.player.has_enqueued()
+            || self.player.progress() + GAPLESS_LEAD < self.total_duration
+        {
+            return;
+        }
+        let Some(file) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
+        let Some(next) = self.upcoming_track(&file) else {
+            return;
+        };
+
+        let track_gain = self.settings.track_gains.get(&next).copied().unwrap_or(0.0);
+        if let Err(err) = self.player.enqueue(&next, track_gain) {
+            self.last_error = Some(format!("Could not play {}: {}", Self::display_name(&next), err));
+        }
+    }
+
+    // Picks up an enqueued track once playback has moved on to it
+    fn follow_gapless_transition(&mut self) {
+        let previous = self.player.current_file().map(ToOwned::to_owned);
+        let Some(file) = self.player.advance_enqueued() else {
+            return;
+        };
+        if previous.as_deref() != Some(file.as_str()) {
+            self.preview = None;
+            self.player.set_replay_gain_db(0.0);
+            self.track_started(&file);
+        }
+    }
+
+    // Loads a track for previewing. It stays paused until the waveform worker has found its
+    // loudest section, then plays a short snippet from there.
+    fn start_preview(&mut self, file: &str) {
+        self.play_file(file);
+        self.resume_offer = None;
+        if self.player.current_file() == Some(file) {
+            self.player.pause();
+            self.preview = Some((file.to_string(), None));
+        }
+    }
+
+    fn update_preview(&mut self) {
+        let Some((file, started)) = &mut self.preview else {
+            return;
+        };
+        if self.player.current_file() != Some(file.as_str()) {
+            // Something else was played in the meantime
+            self.preview = None;
+            return;
+        }
+
+        match started {
+            None if !self.waveform.is_generating() => {
+                let start = self.waveform.loudest_section(PREVIEW_DURATION.as_secs_f32()).unwrap_or(0.0);
+                if let Err(err) = self.player.seek(Duration::from_secs_f32(start)) {
+                    self.last_error = Some(format!("Could not seek the preview: {}", err));
+                }
+                if let Err(err) = self.player.resume() {
+                    self.last_error = Some(format!("Could not start the preview: {}", err));
+                }
+                *started = Some(Instant::now());
+            }
+            Some(started) if started.elapsed() >= PREVIEW_DURATION || self.player.is_finished() => {
+                self.player.stop();
+                self.preview = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Jumps back to the start of the selected range once playback passes its end
+    fn loop_selection(&mut self) {
+        let Some((start, end)) = self.selection else {
+            return;
+        };
+
+        if self.player.is_playing()
+            && self.player.progress() >= end
+            && let Err(err) = self.player.seek(start)
+        {
+            self.last_error = Some(format!("Could not seek: {}", err));
+        }
+    }
+
+    // Collapsible list of upcoming tracks. Entries can be dragged by their handle to reorder,
+    // removed, or clicked to play straight away, which skips the entries ahead of them.
+    fn render_queue(&mut self, ui: &mut egui::Ui) {
+        if self.queue.is_empty() {
+            return;
+        }
+
+        let mut jump_to: Option<usize> = None;
+        let mut remove: Option<usize> = None;
+        let mut reorder: Option<(usize, usize)> = None;
+
+        egui::CollapsingHeader::new(format!("Up next ({})", self.queue.len()))
+            .id_salt("queue_panel")
+            .default_open(true)
+            .show(ui, |ui| {
+                for (index, file) in self.queue.iter().enumerate() {
+                    let row = ui.horizontal(|ui| {
+                        ui.dnd_drag_source(ui.id().with(("queue_entry", index)), index, |ui| {
+                            ui.label("⠿").on_hover_text("Drag to reorder");
+                        });
+                        let name = self.entry_titles.get(file).cloned().unwrap_or_else(|| Self::display_name(file));
+                        if ui.selectable_label(false, name).on_hover_text("Play now").clicked() {
+                            jump_to = Some(index);
+                        }
+                        let remove_response = ui.small_button("✖");
+                        Self::accessible_label(&remove_response, egui::WidgetType::Button, "Remove from queue");
+                        if remove_response.clicked() {
+                            remove = Some(index);
+                        }
+                    });
+
+                    if let Some(from) = row.response.dnd_release_payload::<usize>() {
+                        reorder = Some((*from, index));
+                    }
+                    if row.response.dnd_hover_payload::<usize>().is_some() {
+                        ui.painter().hline(
+                            row.response.rect.x_range(),
+                            row.response.rect.top(),
+                            Stroke::new(2.0, LIGHTER_ACCENT_COLOR),
+                        );
+                    }
+                }
+
+                if ui.small_button("Clear").clicked() {
+                    self.queue.clear();
+                }
+            });
+
+        if let Some((from, to)) = reorder
+            && from != to
+        {
+            let file = self.queue.remove(from);
+            self.queue.insert(to, file);
+        }
+        if let Some(index) = remove {
+            self.queue.remove(index);
+        }
+        if let Some(index) = jump_to {
+            let file = self.queue.drain(..=index).next_back();
+            if let Some(file) = file {
+                self.play_file(&file);
+            }
+        }
+    }
+
+    fn take_queued(&mut self) -> Option<String> {
+        if self.queue.is_empty() {
+            None
+        } else {
+            Some(self.queue.remove(0))
+        }
+    }
+
+    // The entry after `file` in the list, if any
+    fn next_file(&self, file: &str) -> Option<String> {
+        let index = self.audio_files.iter().position(|f| f == file)?;
+        self.audio_files.get(index + 1).cloned()
+    }
+
+    // The entry before `file` in the list, if any
+    fn previous_file(&self, file: &str) -> Option<String> {
+        let index = self.audio_files.iter().position(|f| f == file)?;
+        self.audio_files.get(index.checked_sub(1)?).cloned()
+    }
+
+    // Space toggles playback, arrows seek, Ctrl/Cmd+arrows change track and Esc clears the loop.
+    // Nothing fires while a text field is being edited.
+    fn handle_shortcuts(&mut self, ctx: &Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        // A focused widget handles Space itself, e.g. to click a button or hold mono
+        let widget_focused = ctx.memory(|m| m.focused().is_some());
+        let looping = self.selection.is_some() || self.loop_point_a.is_some();
+        let (toggle, previous, next, back, forward, clear_loop) = ctx.input_mut(|i| {
+            (
+                !widget_focused && i.consume_key(egui::Modifiers::NONE, egui::Key::Space),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::ArrowLeft),
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::ArrowRight),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowLeft),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowRight),
+                looping && i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+
+        if clear_loop {
+            self.selection = None;
+            self.loop_point_a = None;
+        }
+
+        if toggle {
+            self.toggle_playback();
+        }
+        if previous {
+            self.play_previous();
+        }
+        if next {
+            self.play_next();
+        }
+        if (back || forward) && self.player.current_file().is_some() {
+            let position = self.player.progress();
+            let target = if forward { position + SEEK_STEP } else { position.saturating_sub(SEEK_STEP) };
+            if let Err(err) = self.player.seek(target) {
+                self.last_error = Some(format!("Could not seek: {}", err));
+            }
+        }
+    }
+
+    // Pauses while playing, resumes when paused and restarts the current file once it has stopped
+    fn toggle_playback(&mut self) {
+        if self.player.is_playing() {
+            self.player.pause();
+        } else if self.player.is_paused() {
+            if let Err(err) = self.player.resume() {
+                self.last_error = Some(format!("Could not resume playback: {}", err));
+            }
+        } else if let Some(file) = self.player.current_file().map(ToOwned::to_owned) {
+            self.play_file(&file);
+        }
+    }
+
+    // Skips forward like a finished track would, wrapping around when repeating the whole list.
+    // With nothing playing it starts the first file.
+    fn play_next(&mut self) {
+        let next = match self.player.current_file().map(ToOwned::to_owned) {
+            Some(file) => {
+                let next = self.take_queued().or_else(|| self.next_track(&file));
+                if self.repeat_mode == RepeatMode::All {
+                    next.or_else(|| self.audio_files.first().cloned())
+                } else {
+                    next
+                }
+            }
+            None => self.audio_files.first().cloned(),
+        };
+        if let Some(next) = next {
+            self.play_file(&next);
+        }
+    }
+
+    // Steps back in list order, or restarts the current track once it has been playing a while.
+    // With nothing playing it starts the last file.
+    fn play_previous(&mut self) {
+        if self.player.current_file().is_some() && self.player.progress() > PREVIOUS_RESTART_THRESHOLD {
+            if let Err(err) = self.player.seek(Duration::ZERO) {
+                self.last_error = Some(format!("Could not seek: {}", err));
+            }
+            return;
+        }
+
+        let previous = match self.player.current_file() {
+            Some(file) => {
+                let previous = self.previous_file(file);
+                if self.repeat_mode == RepeatMode::All {
+                    previous.or_else(|| self.audio_files.last().cloned())
+                } else {
+                    previous
+                }
+            }
+            None => self.audio_files.last().cloned(),
+        };
+        if let Some(previous) = previous {
+            self.play_file(&previous);
+        }
+    }
+
+    // The track to advance to after `file`: a random unplayed one when shuffling, otherwise the
+    // next in list order
+    fn next_track(&mut self, file: &str) -> Option<String> {
+        if self.shuffle {
+            self.next_shuffled(file)
+        } else {
+            self.next_file(file)
+        }
+    }
+
+    fn next_shuffled(&mut self, file: &str) -> Option<String> {
+        let current = self.audio_files.iter().position(|f| f == file);
+        if let Some(current) = current {
+            self.played_indices.insert(current);
+        }
+
+        let mut candidates: Vec<usize> = (0..self.audio_files.len())
+            .filter(|index| !self.played_indices.contains(index))
+            .collect();
+        if candidates.is_empty() {
+            // Everything has played once; start a new round without repeating the current track
+            self.played_indices.clear();
+            candidates = (0..self.audio_files.len()).filter(|&index| Some(index) != current).collect();
+        }
+
+        let index = *candidates.get(fastrand::usize(..candidates.len().max(1)))?;
+        self.played_indices.insert(index);
+        self.audio_files.get(index).cloned()
+    }
+
+    // Whether anything on screen changes without user input, requiring frequent repaints
+    fn is_animating(&self) -> bool {
+        self.player.is_playing()
+            || self.scan_receiver.is_some()
+            || self.waveform.is_generating()
+            || self.flash_started.is_some()
+            || self.export_job.as_ref().is_some_and(|job| job.result().is_none())
+    }
+
+    // Closes the output device once it has been idle for the configured time
+    fn release_idle_output(&mut self) {
+        let timeout = self.settings.idle_release_secs;
+        if timeout == 0 || !self.player.is_idle() {
+            self.idle_since = None;
+            return;
+        }
+
+        let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+        if idle_since.elapsed() >= Duration::from_secs(timeout as u64) {
+            self.player.release_output();
+            self.idle_since = None;
+        }
+    }
+
+    fn apply_waveform_memory_cap(&mut self) {
+        self.waveform.set_memory_cap(self.settings.waveform_memory_mb as usize * 1024 * 1024);
+    }
+
+    fn apply_silence_threshold(&mut self) {
+        self.waveform.set_silence_threshold_db(self.settings.silence_threshold_db);
+    }
+
+    fn apply_waveform_chunk_size(&mut self) {
+        self.waveform.set_chunk_ms(self.settings.waveform_chunk_ms);
+    }
+
+    fn apply_crossfade_settings(&mut self) {
+        self.player.set_crossfade(
+            Duration::from_secs_f32(self.settings.crossfade_secs),
+            self.settings.crossfade_curve,
+        );
+        self.player.set_fade(Duration::from_millis(self.settings.fade_ms as u64));
+    }
+
+    fn apply_compressor_settings(&mut self) {
+        let compressor = self.player.compressor();
+        compressor.set_settings(self.settings.compressor_preset.settings());
+        compressor.set_enabled(self.settings.night_mode);
+    }
+
+    fn render_sidebar(&mut self, ctx: &Context) {
+        SidePanel::left("side_panel").default_width(300.0).show(ctx, |ui| {
+
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                ui.with_layout(Layout::left_to_right(egui::Align::Center).with_main_justify(true), |ui| {
+                    ui.colored_label(Color32::WHITE, egui::RichText::new("Select file to play").heading());
+                });
+            });
+
+            if !self.audio_files.is_empty() {
+                ui.vertical_centered(|ui| {
+                    ui.label(egui::RichText::new(self.list_summary()).color(Color32::GRAY));
+                });
+            }
+
+            ui.add_space(15.0);
+
+            ui.horizontal(|ui| {
+                let available_width = ui.available_width();
+                let button_size = egui::vec2(100.0, 20.0);
+
+                let indent = (available_width) / 2.0 - button_size.x / 3.0;
+                ui.add_space(indent);
+
+                let button_response = ui.scope(|ui| {
+                    ui.spacing_mut().button_padding = Vec2::new(14.0, 8.0);
+
+                    ui.add(
+                        egui::Button::new(
+                            egui::RichText::new("Browse")
+                                .color(Color32::WHITE)
+                                .size(14.0),
+                        )
+                            .fill(ACCENT_COLOR)
+                            .corner_radius(egui::CornerRadius::same(4)),
+                    )
+
+                });
+
+                if button_response.inner.clicked()
+                    && let Some(dir) = rfd::FileDialog::new().pick_folder()
+                {
+                    self.directory = Some(dir.display().to_string());
+                    self.scan_audio_files();
+                }
+
+                let settings_response = ui.button("⚙").on_hover_text("Settings");
+                Self::accessible_label(&settings_response, egui::WidgetType::Button, "Settings");
+                if settings_response.clicked() {
+                    self.show_settings = !self.show_settings;
+                    if self.show_settings {
+                        self.output_devices = AudioPlayer::output_devices();
+                    }
+                }
+
+                let playlist_response = ui.button("📄").on_hover_text("Open playlist");
+                Self::accessible_label(&playlist_response, egui::WidgetType::Button, "Open playlist");
+                if playlist_response.clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Playlist", &PLAYLIST_EXTENSIONS)
+                        .pick_file()
+                {
+                    self.open_playlist(&path);
+                }
+
+                let histogram_response = ui.button("📊").on_hover_text("Level distribution");
+                Self::accessible_label(&histogram_response, egui::WidgetType::Button, "Level distribution");
+                if histogram_response.clicked() {
+                    self.show_histogram = !self.show_histogram;
+                }
+
+                let jump_response = ui
+                    .add_enabled(self.player.current_file().is_some(), egui::Button::new("◎"))
+                    .on_hover_text("Scroll to current track");
+                Self::accessible_label(&jump_response, egui::WidgetType::Button, "Scroll to current track");
+                if jump_response.clicked() {
+                    self.scroll_to_current = true;
+                }
+            });
+
+            ui.add_space(10.0);
+
+            self.render_bookmarks(ui);
+
+            if self.scan_receiver.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Scanning… {} files found", self.audio_files.len()));
+                });
+            }
+
+            self.render_queue(ui);
+
+            if !self.missing_files.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} missing", self.missing_files.len()));
+                    if ui.small_button("Remove missing").clicked() {
+                        self.remove_missing_files();
+                    }
+                });
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                let previous_sort = self.settings.file_sort;
+                egui::ComboBox::from_id_salt("file_sort")
+                    .selected_text(self.settings.file_sort.label())
+                    .width(110.0)
+                    .show_ui(ui, |ui| {
+                        for key in SortKey::ALL {
+                            ui.selectable_value(&mut self.settings.file_sort, key, key.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Sort the file list");
+                if self.settings.file_sort != previous_sort {
+                    self.settings.file_sort.sort(&mut self.audio_files);
+                    self.played_indices.clear();
+                }
+
+                let search_response = ui.add(
+                    egui::TextEdit::singleline(&mut self.search_query)
+                        .hint_text("🔍 Filter files")
+                        .desired_width(f32::INFINITY),
+                );
+                // Files the filter hides leave the selection so batch actions can't reach them unseen
+                if search_response.changed() {
+                    self.prune_hidden_selection();
+                }
+            });
+
+            let mut file_to_play: Option<String> = None;
+            let mut file_to_select: Option<String> = None;
+            let mut file_to_preview: Option<String> = None;
+            // A file to queue, and whether it goes right after the current track
+            let mut queue_action: Option<(String, bool)> = None;
+            // A file to load into an A/B comparison slot
+            let mut ab_action: Option<(usize, String)> = None;
+            let mut selection_change: Option<(String, SelectionChange)> = None;
+            let mut batch_action: Option<BatchAction> = None;
+            let scroll_to_current = std::mem::take(&mut self.scroll_to_current);
+
+            egui::Frame::default()
+                .inner_margin(egui::Margin::same(8))
+                .show(ui, |ui| {
+                    ScrollArea::vertical().show(ui, |ui| {
+                        for file in &self.audio_files {
+                            let file_name = self.list_title(file);
+                            if !self.matches_search(&file_name) {
+                                continue;
+                            }
+
+                            if self.missing_files.contains(file) {
+                                ui.add_enabled(
+                                    false,
+                                    egui::Label::new(egui::RichText::new(&file_name).strikethrough().weak()),
+                                )
+                                .on_disabled_hover_text("File not found");
+                                continue;
+                            }
+
+                            let is_current = self.player.current_file()
+                                .is_some_and(|current| current == file);
+
+                            let response = ui.selectable_label(is_current, &file_name);
+                            Self::paint_focus_ring(ui, &response);
+
+                            let is_selected = self.selected_files.contains(file)
+                                || self.selected_file.as_deref() == Some(file.as_str());
+                            if is_selected && !is_current {
+                                ui.painter().rect_stroke(
+                                    response.rect,
+                                    2.0,
+                                    Stroke::new(1.0, LIGHTER_ACCENT_COLOR),
+                                    egui::StrokeKind::Inside,
+                                );
+                            }
+
+                            if is_current {
+                                if scroll_to_current {
+                                    response.scroll_to_me(Some(egui::Align::Center));
+                                    self.flash_started = Some(Instant::now());
+                                }
+                                Self::paint_flash(ui, response.rect, &mut self.flash_started);
+                            }
+
+                            let in_multi_selection = self.selected_files.len() > 1 && self.selected_files.contains(file);
+                            let selected_count = self.selected_files.len();
+                            response.context_menu(|ui| {
+                                if in_multi_selection {
+                                    if ui.button(format!("➕ Add {} selected to queue", selected_count)).clicked() {
+                                        batch_action = Some(BatchAction::Queue);
+                                        ui.close_menu();
+                                    }
+                                    if ui.button(format!("💾 Export {} selected…", selected_count)).clicked() {
+                                        batch_action = Some(BatchAction::Export);
+                                        ui.close_menu();
+                                    }
+                                    if ui.button(format!("🗑 Move {} selected to trash", selected_count)).clicked() {
+                                        batch_action = Some(BatchAction::Trash);
+                                        ui.close_menu();
+                                    }
+                                    return;
+                                }
+                                if ui.button("▶ Play now").clicked() {
+                                    file_to_play = Some(file.clone());
+                                    ui.close_menu();
+                                }
+                                if ui.button("⤴ Play next").clicked() {
+                                    queue_action = Some((file.clone(), true));
+                                    ui.close_menu();
+                                }
+                                if ui.button("➕ Add to queue").clicked() {
+                                    queue_action = Some((file.clone(), false));
+                                    ui.close_menu();
+                                }
+                                if ui.button("🗑 Move to trash").clicked() {
+                                    selection_change = Some((file.clone(), SelectionChange::Only));
+                                    batch_action = Some(BatchAction::Trash);
+                                    ui.close_menu();
+                                }
+                                ui.separator();
+                                if ui.button("Load into A").clicked() {
+                                    ab_action = Some((0, file.clone()));
+                                    ui.close_menu();
+                                }
+                                if ui.button("Load into B").clicked() {
+                                    ab_action = Some((1, file.clone()));
+                                    ui.close_menu();
+                                }
+                            });
+
+                            // Ctrl/Cmd-click toggles a file in the selection, Shift-click extends it
+                            let modifiers = ui.input(|i| i.modifiers);
+                            if response.clicked() && modifiers.command {
+                                selection_change = Some((file.clone(), SelectionChange::Toggle));
+                                continue;
+                            }
+                            if response.clicked() && modifiers.shift {
+                                selection_change = Some((file.clone(), SelectionChange::Extend));
+                                continue;
+                            }
+
+                            match self.settings.click_action {
+                                ClickAction::PlayOnClick => {
+                                    if response.clicked() {
+                                        file_to_play = Some(file.clone());
+                                    }
+                                }
+                                ClickAction::DoubleClickToPlay => {
+                                    if response.double_clicked() {
+                                        file_to_play = Some(file.clone());
+                                    } else if response.clicked() {
+                                        file_to_select = Some(file.clone());
+                                    }
+                                }
+                                ClickAction::PreviewOnClick => {
+                                    if response.double_clicked() {
+                                        file_to_play = Some(file.clone());
+                                    } else if response.clicked() {
+                                        file_to_preview = Some(file.clone());
+                                    }
+                                }
+                            }
+                        }
+                    });
+                });
+            if let Some(file) = file_to_select {
+                self.select_only(file);
+            }
+            if let Some((file, change)) = selection_change {
+                self.change_selection(file, change);
+            }
+            if let Some(action) = batch_action {
+                self.apply_batch_action(action);
+            }
+            if let Some(file) = file_to_preview {
+                self.select_only(file.clone());
+                self.start_preview(&file);
+            }
+            if let Some((file, play_next)) = queue_action {
+                self.enqueue(file, play_next);
+            }
+            if let Some((slot, file)) = ab_action {
+                self.ab_slots[slot] = Some(file);
+                self.ab_loudness[slot] = None;
+            }
+            if let Some(file) = file_to_play {
+                self.select_only(file.clone());
+                self.play_file(&file);
+            }
+        });
+    }
+
+    // Name a file is listed under: its playlist title if it has one, otherwise its file name
+    fn list_title(&self, file: &str) -> String {
+        self.entry_titles.get(file).cloned().unwrap_or_else(|| Self::display_name(file))
+    }
+
+    fn matches_search(&self, title: &str) -> bool {
+        let query = self.search_query.trim().to_lowercase();
+        query.is_empty() || title.to_lowercase().contains(&query)
+    }
+
+    // Files shown as selectable rows, in list order: those passing the filter and still on disk
+    fn visible_files(&self) -> Vec<String> {
+        self.audio_files
+            .iter()
+            .filter(|file| !self.missing_files.contains(*file) && self.matches_search(&self.list_title(file)))
+            .cloned()
+            .collect()
+    }
+
+    fn prune_hidden_selection(&mut self) {
+        let visible: HashSet<String> = self.visible_files().into_iter().collect();
+        self.selected_files.retain(|file| visible.contains(file));
+    }
+
+    fn select_only(&mut self, file: String) {
+        self.selected_files.clear();
+        self.selected_files.insert(file.clone());
+        self.selected_file = Some(file);
+    }
+
+    fn change_selection(&mut self, file: String, change: SelectionChange) {
+        match change {
+            SelectionChange::Only => self.select_only(file),
+            SelectionChange::Toggle => {
+                if !self.selected_files.remove(&file) {
+                    self.selected_files.insert(file.clone());
+                }
+                self.selected_file = Some(file);
+            }
+            SelectionChange::Extend => {
+                // The range spans visible rows only, so rows hidden between the two ends stay out
+                let visible = self.visible_files();
+                let anchor = self
+                    .selected_file
+                    .as_ref()
+                    .and_then(|anchor| visible.iter().position(|f| f == anchor));
+                let target = visible.iter().position(|f| *f == file);
+                let (Some(anchor), Some(target)) = (anchor, target) else {
+                    self.select_only(file);
+                    return;
+                };
+                // The anchor stays put so further Shift-clicks re-span from it
+                let range = anchor.min(target)..=anchor.max(target);
+                self.selected_files = visible[range].iter().cloned().collect();
+            }
+        }
+    }
+
+    // Selected files in list order, leaving out any the list doesn't currently show
+    fn selected_in_order(&self) -> Vec<String> {
+        self.visible_files()
+            .into_iter()
+            .filter(|file| self.selected_files.contains(file))
+            .collect()
+    }
+
+    fn apply_batch_action(&mut self, action: BatchAction) {
+        let files = self.selected_in_order();
+        if files.is_empty() {
+            return;
+        }
+
+        match action {
+            BatchAction::Queue => {
+                for file in files {
+                    self.enqueue(file, false);
+                }
+            }
+            BatchAction::Export => {
+                let Some(folder) = rfd::FileDialog::new().set_title("Export selected to").pick_folder() else {
+                    return;
+                };
+                // Destinations already claimed by queued exports, so none of them write to the same file
+                let mut taken: HashSet<PathBuf> = self.pending_exports.iter().map(|(_, dest)| dest.clone()).collect();
+                for file in files {
+                    let dest = Self::export_destination(&folder, &file, &taken);
+                    taken.insert(dest.clone());
+                    self.pending_exports.push((file, dest));
+                }
+                self.start_pending_export();
+            }
+            BatchAction::Trash => self.trash_files(files),
+        }
+    }
+
+    // "<stem>.wav" in `folder`, numbered "<stem> (2).wav" and up when that name is already on disk
+    // or claimed by another export. Files on disk are never overwritten, which also rules out
+    // writing over the source.
+    fn export_destination(folder: &Path, source: &str, taken: &HashSet<PathBuf>) -> PathBuf {
+        let source = Path::new(source);
+        let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = ExportFormat::Wav.extension();
+        let source = source.canonicalize().unwrap_or_else(|_| source.to_path_buf());
+
+        let mut candidate = folder.join(format!("{}.{}", stem, extension));
+        let mut number = 2;
+        while candidate.exists() || taken.contains(&candidate) || candidate == source {
+            candidate = folder.join(format!("{} ({}).{}", stem, number, extension));
+            number += 1;
+        }
+        candidate
+    }
+
+    // The file name part of a path, for messages
+    fn display_name(file: &str) -> String {
+        Path::new(file).file_name().unwrap_or_default().to_string_lossy().into_owned()
+    }
+
+    // Moves files to the trash after confirming, dropping them from the list and queue
+    fn trash_files(&mut self, files: Vec<String>) {
+        let description = match files.as_slice() {
+            [file] => format!("Move \"{}\" to the trash?", Self::display_name(file)),
+            _ => {
+                let mut description = format!("Move {} files to the trash?\n", files.len());
+                for file in files.iter().take(TRASH_CONFIRM_NAMES) {
+                    description.push_str(&format!("\n{}", Self::display_name(file)));
+                }
+                if files.len() > TRASH_CONFIRM_NAMES {
+                    description.push_str(&format!("\n… and {} more", files.len() - TRASH_CONFIRM_NAMES));
+                }
+                description
+            }
+        };
+        let confirmed = rfd::MessageDialog::new()
+            .set_title("Move to trash")
+            .set_description(description)
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .show();
+        if confirmed != rfd::MessageDialogResult::Ok {
+            return;
+        }
+
+        let mut failures = Vec::new();
+        for file in files {
+            if self.player.current_file() == Some(file.as_str()) {
+                self.player.stop();
+            }
+            match move_to_trash(Path::new(&file)) {
+                Ok(()) => {
+                    self.played_indices.clear();
+                    self.audio_files.retain(|f| *f != file);
+                    self.queue.retain(|f| *f != file);
+                    self.selected_files.remove(&file);
+                }
+                Err(err) => failures.push(format!("Could not move {} to trash: {}", Self::display_name(&file), err)),
+            }
+        }
+        if !failures.is_empty() {
+            self.last_error = Some(failures.join("\n"));
+        }
+    }
+
+    // Starts the next queued export once the current one has finished
+    fn start_pending_export(&mut self) {
+        if self.pending_exports.is_empty() || self.export_job.as_ref().is_some_and(|job| job.result().is_none()) {
+            return;
+        }
+        if let Some(Some(Err(err))) = self.export_job.as_ref().map(ExportJob::result) {
+            self.last_error = Some(format!("Export failed: {}", err));
+        }
+
+        let (source, dest) = self.pending_exports.remove(0);
+        self.export_job = Some(ExportJob::start(&source, dest, ExportFormat::Wav, None));
+    }
+
+    // Lists the current track's bookmarks with controls to add, rename, delete and jump to them
+    fn render_bookmarks(&mut self, ui: &mut egui::Ui) {
+        let Some(file) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
+
+        let count = self.settings.bookmarks.get(&file).map_or(0, Vec::len);
+        let mut seek_to = None;
+
+        egui::CollapsingHeader::new(format!("Bookmarks ({})", count))
+            .id_salt("bookmarks")
+            .show(ui, |ui| {
+                if ui.button("+ Add at current position").clicked() {
+                    let position = self.player.progress();
+                    let bookmarks = self.settings.bookmarks.entry(file.clone()).or_default();
+                    bookmarks.push((position, format!("Bookmark {}", bookmarks.len() + 1)));
+                    bookmarks.sort_by_key(|(position, _)| *position);
+                }
+
+                let Some(bookmarks) = self.settings.bookmarks.get_mut(&file) else {
+                    return;
+                };
+
+                let mut to_delete = None;
+                for (index, (position, name)) in bookmarks.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button(format_duration(*position)).clicked() {
+                            seek_to = Some(*position);
+                        }
+                        ui.add(egui::TextEdit::singleline(name).desired_width(140.0));
+                        let delete_response = ui.small_button("🗑").on_hover_text("Delete bookmark");
+                        Self::accessible_label(&delete_response, egui::WidgetType::Button, "Delete bookmark");
+                        if delete_response.clicked() {
+                            to_delete = Some(index);
+                        }
+                    });
+                }
+
+                if let Some(index) = to_delete {
+                    bookmarks.remove(index);
+                    if bookmarks.is_empty() {
+                        self.settings.bookmarks.remove(&file);
+                    }
+                }
+            });
+
+        if let Some(position) = seek_to
+            && let Err(err) = self.player.seek(position)
+        {
+            self.last_error = Some(format!("Could not seek: {}", err));
+        }
+    }
+
+    // Fades a highlight over the current row after a jump so it is easy to spot
+    fn paint_flash(ui: &egui::Ui, rect: Rect, flash_started: &mut Option<Instant>) {
+        let Some(started) = *flash_started else {
+            return;
+        };
+
+        let elapsed = started.elapsed();
+        if elapsed >= FLASH_DURATION {
+            *flash_started = None;
+            return;
+        }
+
+        let strength = 1.0 - elapsed.as_secs_f32() / FLASH_DURATION.as_secs_f32();
+        ui.painter().rect_filled(rect, 2.0, LIGHTER_ACCENT_COLOR.gamma_multiply(strength * 0.6));
+    }
+
+    pub fn render_main_panel(&mut self, ctx: &Context) {
+        CentralPanel::default().show(ctx, |ui| {
+            let available_width = ui.available_width();
+            let available_height = ui.available_height();
+
+            let waveform_height = available_height * 0.5;
+            let play_bar_height = available_height * 0.2;
+
+            ui.with_layout(Layout::top_down(egui::Align::Min), |ui| {
+
+                ui.allocate_ui(Vec2::new(available_width, waveform_height), |ui| {
+                    self.render_track_header(ui);
+                    self.render_waveform_overview(ui);
+                    self.render_waveform(ui);
+                });
+
+                ui.add_space(10.0);
+
+                ui.allocate_ui(Vec2::new(available_width, play_bar_height), |ui| {
+                    let progress = self.player.progress();
+                    let progress_secs = progress.as_secs();
+                    let total_secs = self.total_duration.as_secs();
+                    let format_time = if self.settings.precise_time_display {
+                        format_duration_precise
+                    } else {
+                        format_duration
+                    };
+
+                    let mut ratio = if total_secs > 0 {
+                        (progress_secs as f32 / total_secs as f32).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+
+                    let bar_height = 6.0;
+                    let horizontal_padding = 12.0;
+
+                    ui.add_space(5.0); // vertical margin (top)
+
+                    let (outer_rect, bar_response) = ui.allocate_exact_size(
+                        Vec2::new(available_width, bar_height),
+                        egui::Sense::click_and_drag(),
+                    );
+                    let total_label = if self.duration_known {
+                        format_time(self.total_duration)
+                    } else {
+                        "--:--".to_string()
+                    };
+                    bar_response.widget_info(|| {
+                        let of = if self.duration_known {
+                            format!("of {}", format_duration(self.total_duration))
+                        } else {
+                            "of unknown length".to_string()
+                        };
+                        egui::WidgetInfo::slider(
+                            total_secs > 0,
+                            ratio as f64,
+                            format!("Playback position {} {}", format_duration(progress), of),
+                        )
+                    });
+
+                    let bar_rect = Rect {
+                        min: outer_rect.min + Vec2::new(horizontal_padding, 0.0),
+                        max: outer_rect.max - Vec2::new(horizontal_padding, 0.0),
+                    };
+
+                    // Clicking jumps to a position; dragging scrubs continuously while held
+                    let scrubbed = bar_response.dragged() && bar_response.drag_delta().x != 0.0;
+                    if (bar_response.clicked() || bar_response.drag_started() || scrubbed)
+                        && !self.total_duration.is_zero()
+                        && let Some(pointer) = bar_response.interact_pointer_pos()
+                    {
+                        ratio = ((pointer.x - bar_rect.left()) / bar_rect.width()).clamp(0.0, 1.0);
+                        if let Err(err) = self.player.seek(self.total_duration.mul_f32(ratio)) {
+                            self.last_error = Some(format!("Could not seek: {}", err));
+                        }
+                    }
+
+                    ui.painter().rect_filled(bar_rect, 3.0, LIGHTER_ACCENT_COLOR);
+                    let played_rect = Rect {
+                        min: bar_rect.min,
+                        max: egui::pos2(bar_rect.min.x + bar_rect.width() * ratio, bar_rect.max.y),
+                    };
+                    ui.painter().rect_filled(played_rect, 3.0, ACCENT_COLOR);
+                    // Without a known length there is nothing to fill, so a segment sweeps along instead
+                    if !self.duration_known && self.player.is_playing() {
+                        let segment = bar_rect.width() * 0.2;
+                        let phase = (ui.input(|i| i.time) * 0.5).fract() as f32;
+                        let left = bar_rect.left() + (bar_rect.width() + segment) * phase - segment;
+                        let sweep = Rect::from_min_max(
+                            egui::pos2(left.max(bar_rect.left()), bar_rect.top()),
+                            egui::pos2((left + segment).min(bar_rect.right()), bar_rect.bottom()),
+                        );
+                        ui.painter().rect_filled(sweep, 3.0, ACCENT_COLOR);
+                    }
+                    if bar_response.has_focus() {
+                        ui.painter().rect_stroke(
+                            bar_rect.expand(3.0),
+                            4.0,
+                            Stroke::new(2.0, Color32::WHITE),
+                            egui::StrokeKind::Outside,
+                        );
+                    }
+
+                    ui.add_space(5.0);
+
+                    ui.horizontal(|ui| {
+                        ui.add_space(horizontal_padding);
+                        ui.label(format_time(progress));
+                        ui.add_space(horizontal_padding);
+                        self.render_volume_slider(ui);
+
+                        ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.add_space(horizontal_padding);
+                            ui.label(total_label);
+                            ui.add_space(horizontal_padding);
+                            self.render_correlation_meter(ui);
+                            ui.add_space(horizontal_padding);
+                            self.render_clip_led(ui);
+                        });
+                    });
+                });
+
+                self.render_track_gain(ui);
+                self.render_navigation(ui);
+                self.render_playback_error(ui);
+                self.render_last_error(ui);
+                self.render_resume_offer(ui);
+                self.render_export_status(ui);
+                self.render_ab_controls(ui);
+
+                ui.with_layout(Layout::centered_and_justified(egui::Direction::LeftToRight), |ui| {
+                    ui.horizontal(|ui| {
+                        let total_button_width = 4.0 * 40.0;
+                        let available_width = ui.available_width();
+                        let spacing = (available_width - total_button_width) / 3.0;
+
+                        if spacing > 0.0 {
+                            ui.add_space(spacing);
+                        }
+
+                        let has_files = !self.audio_files.is_empty();
+                        let previous_response =
+                            ui.add_enabled_ui(has_files, |ui| AudioPlayerApp::styled_icon_button(ui, "Previous", "⏮"));
+                        if previous_response.inner.clicked() {
+                            self.play_previous();
+                        }
+
+                        let play_response = AudioPlayerApp::styled_icon_button(ui, "Play", "▶");
+                        if play_response.clicked() {
+                            let file_path = self.player.current_file().map(ToOwned::to_owned);
+                            if let Some(file_path) = file_path {
+                                // If a file was paused, resume it
+                                if self.player.is_paused() {
+                                    if let Err(e) = self.player.resume() {
+                                        self.last_error = Some(format!("Could not resume playback: {}", e));
+                                    }
+
+                                } else {
+                                    // Otherwise play the current file again
+                                    self.play_file(&file_path);
+                                }
+                            }
+                        }
+
+                        let pause_response = AudioPlayerApp::styled_icon_button(ui, "Pause", "⏸");
+                        if pause_response.clicked() {
+                            self.player.pause();
+                        }
+
+                        let stop_response = AudioPlayerApp::styled_icon_button(ui, "Stop", "⏹");
+                        if stop_response.clicked() {
+                            self.remember_position();
+                            self.player.stop();
+                        }
+
+                        let next_response =
+                            ui.add_enabled_ui(has_files, |ui| AudioPlayerApp::styled_icon_button(ui, "Next", "⏭"));
+                        if next_response.inner.clicked() {
+                            self.play_next();
+                        }
+
+                        let (mute_label, mute_icon) = if self.player.is_muted() { ("Unmute", "🔇") } else { ("Mute", "🔊") };
+                        if AudioPlayerApp::styled_icon_button(ui, mute_label, mute_icon).clicked() {
+                            self.player.toggle_mute();
+                        }
+
+                        let export_response = ui.add_enabled_ui(
+                            self.player.current_file().is_some()
+                                && self.export_job.as_ref().is_none_or(|job| job.result().is_some()),
+                            |ui| AudioPlayerApp::styled_icon_button(ui, "Export", "💾"),
+                        );
+                        if export_response.inner.clicked() {
+                            self.export_current_file();
+                        }
+
+                        let shuffle_response = ui.selectable_label(self.shuffle, "🔀").on_hover_text("Shuffle");
+                        shuffle_response.widget_info(|| {
+                            egui::WidgetInfo::selected(egui::WidgetType::Checkbox, true, self.shuffle, "Shuffle")
+                        });
+                        if shuffle_response.clicked() {
+                            self.shuffle = !self.shuffle;
+                            self.played_indices.clear();
+                        }
+
+                        self.render_repeat_controls(ui);
+                        self.render_pitch_control(ui);
+                        self.render_speed_controls(ui);
+
+                        let mono_response = ui
+                            .add(egui::Button::new("Mono").sense(egui::Sense::click_and_drag()))
+                            .on_hover_text("Hold to check the mix in mono");
+                        Self::paint_focus_ring(ui, &mono_response);
+                        // Holding Space while the button has keyboard focus works like holding the mouse
+                        let key_held = mono_response.has_focus() && ui.input(|i| i.key_down(egui::Key::Space));
+                        let hold_mono = (mono_response.is_pointer_button_down_on() || key_held)
+                            && !self.player.is_paused();
+                        self.player.mono_sum().set_enabled(hold_mono);
+
+                        self.render_level_meters(ui);
+                    });
+                });
+
+            });
+        });
+    }
+
+
+    // Left and right level meters: a bright bar for the peak, a darker one for RMS, and a box on
+    // top that lights red for a while after the channel reaches 0 dBFS
+    fn render_level_meters(&mut self, ui: &mut egui::Ui) {
+        let levels = if self.player.is_playing() {
+            self.waveform.channel_levels_at(self.player.progress().as_secs_f32(), METER_WINDOW_SECS)
+        } else {
+            None
+        };
+        let elapsed = ui.input(|i| i.stable_dt).min(0.1);
+        let to_db = |level: f32| (20.0 * level.max(1e-6).log10()).max(METER_FLOOR_DB);
+        let height_of = |db: f32| ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+
+        let (rect, response) = ui.allocate_exact_size(Vec2::new(22.0, 30.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let clip_height = 4.0;
+        for channel in 0..2 {
+            let (peak, rms) = levels.map_or((0.0, 0.0), |levels| levels[channel]);
+            let fallen = self.meter_levels[channel] - METER_FALL_DB_PER_SEC * elapsed;
+            self.meter_levels[channel] = to_db(peak).max(fallen).max(METER_FLOOR_DB);
+            if peak >= CLIP_THRESHOLD {
+                self.meter_clipped_at[channel] = Some(Instant::now());
+            }
+
+            let left = rect.left() + channel as f32 * rect.width() / 2.0;
+            let column = Rect::from_min_max(
+                egui::pos2(left + 1.0, rect.top() + clip_height + 1.0),
+                egui::pos2(left + rect.width() / 2.0 - 1.0, rect.bottom()),
+            );
+            painter.rect_filled(column, 0.0, Color32::from_gray(30));
+            let bar = |level: f32| {
+                Rect::from_min_max(egui::pos2(column.left(), column.bottom() - level * column.height()), column.right_bottom())
+            };
+            painter.rect_filled(bar(height_of(self.meter_levels[channel])), 0.0, Color32::LIGHT_GREEN);
+            painter.rect_filled(bar(height_of(to_db(rms))), 0.0, Color32::DARK_GREEN);
+
+            let clipped = self.meter_clipped_at[channel].is_some_and(|at| at.elapsed() < CLIP_HOLD);
+            let clip_rect = Rect::from_min_size(egui::pos2(column.left(), rect.top()), Vec2::new(column.width(), clip_height));
+            painter.rect_filled(clip_rect, 0.0, if clipped { Color32::RED } else { Color32::from_gray(60) });
+        }
+
+        let [left_db, right_db] = self.meter_levels;
+        response.on_hover_text(format!("Peak L {:.1} dBFS, R {:.1} dBFS", left_db, right_db));
+    }
+
+    // Draws a -1..+1 phase correlation meter for stereo files; hidden for mono sources
+    fn render_correlation_meter(&mut self, ui: &mut egui::Ui) {
+        if self.waveform.get_channel_buffers().is_none() {
+            return;
+        }
+
+        let position = self.player.progress().as_secs_f32();
+        if let Some(correlation) = self.waveform.correlation_at(position, CORRELATION_WINDOW_SECS) {
+            self.phase_correlation += (correlation - self.phase_correlation) * 0.3;
+        }
+
+        let (rect, response) = ui.allocate_exact_size(Vec2::new(120.0, 10.0), egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, Color32::from_gray(40));
+        painter.vline(rect.center().x, rect.y_range(), Stroke::new(1.0, Color32::GRAY));
+
+        let marker_x = rect.center().x + self.phase_correlation * rect.width() / 2.0;
+        let marker_color = if self.phase_correlation >= 0.0 { Color32::LIGHT_GREEN } else { Color32::LIGHT_RED };
+        painter.vline(marker_x, rect.y_range(), Stroke::new(3.0, marker_color));
+
+        response.on_hover_text(format!("Phase correlation {:+.2}", self.phase_correlation));
+        ui.label("Phase");
+    }
+
+    // Peak-hold LED that lights when audio near the playhead clips; clicking it resets the hold
+    fn render_clip_led(&mut self, ui: &mut egui::Ui) {
+        if self.player.current_file().is_none() {
+            return;
+        }
+
+        let position = self.player.progress().as_secs_f32();
+        if self.player.is_playing()
+            && self
+                .waveform
+                .peak_at(position, CORRELATION_WINDOW_SECS)
+                .is_some_and(|peak| peak >= CLIP_THRESHOLD)
+        {
+            self.clip_detected_at = Some(Instant::now());
+        }
+        let lit = self.clip_detected_at.is_some_and(|at| at.elapsed() < CLIP_HOLD);
+
+        let (rect, response) = ui.allocate_exact_size(Vec2::splat(10.0), egui::Sense::click());
+        let color = if lit { Color32::RED } else { Color32::from_gray(60) };
+        ui.painter().circle_filled(rect.center(), 5.0, color);
+
+        if response.clicked() {
+            self.clip_detected_at = None;
+        }
+        Self::accessible_label(&response, egui::WidgetType::Button, if lit { "Clipping" } else { "No clipping" });
+        response.on_hover_text("Lights when the audio clips; click to reset");
+        ui.label("Clip");
+    }
+
+    // Slot buttons for A/B comparison; clicking a slot switches to it at the current position
+    fn render_ab_controls(&mut self, ui: &mut egui::Ui) {
+        if self.ab_slots.iter().all(Option::is_none) {
+            return;
+        }
+
+        let active = self.active_ab_slot();
+        let mut switch_to = None;
+        let mut clear = false;
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.label("Compare");
+            for (slot, name) in ["A", "B"].into_iter().enumerate() {
+                let file_name = self.ab_slots[slot]
+                    .as_deref()
+                    .and_then(|file| Path::new(file).file_name())
+                    .map(|name| name.to_string_lossy().to_string());
+                let text = format!("{}: {}", name, file_name.as_deref().unwrap_or("—"));
+                let response = ui.add_enabled(
+                    file_name.is_some(),
+                    egui::SelectableLabel::new(active == Some(slot), text),
+                );
+                if response.clicked() {
+                    switch_to = Some(slot);
+                }
+            }
+
+            let matched = self.ab_loudness.iter().all(Option::is_some);
+            if active.is_some() {
+                ui.label(egui::RichText::new(if matched { "level matched" } else { "measuring…" }).weak());
+            }
+            if ui.small_button("Clear").clicked() {
+                clear = true;
+            }
+        });
+
+        if let Some(slot) = switch_to {
+            self.switch_ab_slot(slot);
+        }
+        if clear {
+            self.ab_slots = [None, None];
+            self.ab_loudness = [None, None];
+            self.player.set_match_gain_db(0.0);
+        }
+    }
+
+    // The comparison slot that is currently playing, if any
+    fn active_ab_slot(&self) -> Option<usize> {
+        let current = self.player.current_file()?;
+        (self.ab_slots[self.ab_active].as_deref() == Some(current)).then_some(self.ab_active)
+    }
+
+    // Switches to the other comparison track immediately, keeping the playhead where it was
+    fn switch_ab_slot(&mut self, slot: usize) {
+        let Some(file) = self.ab_slots[slot].clone() else {
+            return;
+        };
+        let position = if self.active_ab_slot().is_some() {
+            self.player.progress()
+        } else {
+            Duration::ZERO
+        };
+
+        // A/B switches should be instant regardless of the crossfade and fade settings, and are
+        // comparisons rather than leaving a track, so no position is remembered or offered
+        self.player.set_crossfade(Duration::ZERO, self.settings.crossfade_curve);
+        self.player.set_fade(Duration::ZERO);
+        let started = self.start_playback(&file);
+        self.apply_crossfade_settings();
+        if !started {
+            return;
+        }
+        self.resume_offer = None;
+
+        self.ab_active = slot;
+        if let Err(err) = self.player.seek(position) {
+            self.last_error = Some(format!("Could not keep the position when switching tracks: {}", err));
+        }
+        self.apply_ab_match_gain();
+    }
+
+    // Records the active slot's level once its waveform analysis finishes
+    fn update_ab_levels(&mut self) {
+        let Some(slot) = self.active_ab_slot() else {
+            return;
+        };
+        if self.ab_loudness[slot].is_none()
+            && let Some(loudness) = self.waveform.get_loudness_db()
+        {
+            self.ab_loudness[slot] = Some(loudness);
+            self.apply_ab_match_gain();
+        }
+    }
+
+    // Turns the louder track down to the level of the quieter one, never boosting
+    fn apply_ab_match_gain(&mut self) {
+        let Some(slot) = self.active_ab_slot() else {
+            return;
+        };
+        if let [Some(a), Some(b)] = self.ab_loudness {
+            self.player.set_match_gain_db(a.min(b) - [a, b][slot]);
+        }
+    }
+
+    // Buttons that seek using the analysed waveform: to the loudest point or between silent gaps
+    fn render_navigation(&mut self, ui: &mut egui::Ui) {
+        if self.player.current_file().is_none() {
+            return;
+        }
+
+        let ready = !self.waveform.is_generating();
+        let position = self.player.progress().as_secs_f32();
+        let mut target = None;
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.label("Jump to");
+            ui.add_enabled_ui(ready, |ui| {
+                if ui.button("Loudest point").clicked() {
+                    target = self.waveform.loudest_point();
+                }
+                // Step back past a gap that just ended so repeated presses keep moving
+                if ui.button("⏪ Previous gap").clicked() {
+                    target = Some(self.waveform.previous_gap_end(position - 1.0).unwrap_or(0.0));
+                }
+                if ui.button("Next gap ⏩").clicked() {
+                    target = self.waveform.next_gap_end(position);
+                }
+            });
+        });
+
+        if let Some(target) = target
+            && let Err(err) = self.player.seek(Duration::from_secs_f32(target.max(0.0)))
+        {
+            self.last_error = Some(format!("Could not seek: {}", err));
+        }
+    }
+
+    // Lets the user nudge the level of the playing track; the offset is remembered per file
+    fn render_track_gain(&mut self, ui: &mut egui::Ui) {
+        let Some(file) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            let label = ui.label("Track gain");
+
+            let mut gain_db = self.player.track_gain_db();
+            let mut changed = ui
+                .add(egui::DragValue::new(&mut gain_db).range(-12.0..=12.0).speed(0.1).suffix(" dB"))
+                .labelled_by(label.id)
+                .changed();
+            if gain_db != 0.0 && ui.small_button("Reset").clicked() {
+                gain_db = 0.0;
+                changed = true;
+            }
+
+            if changed {
+                self.player.set_track_gain_db(gain_db);
+                if gain_db == 0.0 {
+                    self.settings.track_gains.remove(&file);
+                } else {
+                    self.settings.track_gains.insert(file, gain_db);
+                }
+            }
+        });
+    }
+
+    // Banner for a track that stopped decoding partway, with an option to reopen it where it stopped
+    fn render_playback_error(&mut self, ui: &mut egui::Ui) {
+        let Some((file, position)) = self.playback_error.clone() else {
+            return;
+        };
+
+        let mut retry = false;
+        let mut dismiss = false;
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.colored_label(Color32::LIGHT_RED, "Playback error, the file may have changed");
+            retry = ui.button("Retry").on_hover_text("Reopen the file and continue from where it stopped").clicked();
+            let dismiss_response = ui.small_button("✖");
+            Self::accessible_label(&dismiss_response, egui::WidgetType::Button, "Dismiss");
+            dismiss = dismiss_response.clicked();
+        });
+
+        if retry {
+            self.play_file(&file);
+            if self.player.current_file() == Some(file.as_str())
+                && let Err(err) = self.player.seek(position)
+            {
+                self.last_error = Some(format!("Could not seek: {}", err));
+            }
+        } else if dismiss {
+            self.playback_error = None;
+        }
+    }
+
+    fn render_last_error(&mut self, ui: &mut egui::Ui) {
+        let Some(message) = &self.last_error else {
+            return;
+        };
+
+        let mut dismiss = false;
+        egui::Frame::default()
+            .fill(Color32::from_rgb(0x5a, 0x1a, 0x1a))
+            .corner_radius(egui::CornerRadius::same(4))
+            .inner_margin(egui::Margin::symmetric(8, 4))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(Color32::LIGHT_RED, message);
+                    let dismiss_response = ui.small_button("✖");
+                    Self::accessible_label(&dismiss_response, egui::WidgetType::Button, "Dismiss");
+                    dismiss = dismiss_response.clicked();
+                });
+            });
+
+        if dismiss {
+            self.last_error = None;
+        }
+    }
+
+    fn render_export_status(&mut self, ui: &mut egui::Ui) {
+        let Some(job) = &self.export_job else {
+            return;
+        };
+        let pending = self.pending_exports.len();
+
+        let mut dismiss = false;
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            match job.result() {
+                None => {
+                    let progress = job.progress();
+                    ui.add(
+                        egui::ProgressBar::new(progress.unwrap_or(0.0))
+                            .desired_width(200.0)
+                            .show_percentage()
+                            .animate(progress.is_none()),
+                    );
+                    if pending > 0 {
+                        ui.label(format!("{} more queued", pending));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        job.cancel();
+                        self.pending_exports.clear();
+                    }
+                }
+                Some(Ok(path)) => {
+                    ui.label(format!("Exported to {}", path.display()));
+                    let dismiss_response = ui.small_button("✖");
+                    Self::accessible_label(&dismiss_response, egui::WidgetType::Button, "Dismiss");
+                    dismiss = dismiss_response.clicked();
+                }
+                Some(Err(err)) => {
+                    ui.colored_label(Color32::LIGHT_RED, format!("Export failed: {}", err));
+                    let dismiss_response = ui.small_button("✖");
+                    Self::accessible_label(&dismiss_response, egui::WidgetType::Button, "Dismiss");
+                    dismiss = dismiss_response.clicked();
+                }
+            }
+        });
 
-                    ui.add_space(5.0); // vertical margin (top)
+        if dismiss {
+            self.export_job = None;
+        }
+    }
 
-                    let (outer_rect, _) = ui.allocate_exact_size(
-                        Vec2::new(available_width, bar_height),
-                        egui::Sense::hover(),
-                    );
+    fn export_current_file(&mut self) {
+        let Some(source) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
 
-                    let bar_rect = Rect {
-                        min: outer_rect.min + Vec2::new(horizontal_padding, 0.0),
-                        max: outer_rect.max - Vec2::new(horizontal_padding, 0.0),
-                    };
+        let stem = Path::new(&source)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        // Formats this build can't encode aren't offered
+        let mut dialog = rfd::FileDialog::new().set_file_name(format!("{}.wav", stem));
+        for format in ExportFormat::ALL {
+            if format.unavailable_reason().is_none() {
+                dialog = dialog.add_filter(format.label(), &[format.extension()]);
+            }
+        }
 
-                    ui.painter().rect_filled(bar_rect, 3.0, LIGHTER_ACCENT_COLOR);
-                    let played_rect = Rect {
-                        min: bar_rect.min,
-                        max: egui::pos2(bar_rect.min.x + bar_rect.width() * ratio, bar_rect.max.y),
-                    };
-                    ui.painter().rect_filled(played_rect, 3.0, ACCENT_COLOR);
+        if let Some(dest) = dialog.save_file() {
+            let (dest, format) = match ExportFormat::from_path(&dest) {
+                Some(format) => (dest, format),
+                None => (dest.with_extension("wav"), ExportFormat::Wav),
+            };
+            if let Some(reason) = format.unavailable_reason() {
+                self.last_error = Some(format!("Could not export {}: {}", dest.display(), reason));
+                return;
+            }
+            self.export_job = Some(ExportJob::start(&source, dest, format, self.selection));
+        }
+    }
 
-                    ui.add_space(5.0);
+    // Cover art next to the title of the playing track, with artist, album and year underneath
+    // when tagged
+    fn render_track_header(&mut self, ui: &mut egui::Ui) {
+        if self.player.current_file().is_none() {
+            return;
+        }
 
-                    ui.horizontal(|ui| {
-                        ui.add_space(horizontal_padding);
-                        ui.label(format!("{:02}:{:02}", progress_secs / 60, progress_secs % 60));
+        ui.horizontal(|ui| {
+            self.render_track_info(ui);
+            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.add_space(8.0);
+                self.render_spectrum_bars(ui);
+            });
+        });
+    }
 
-                        ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
-                            ui.add_space(horizontal_padding);
-                            ui.label(format!("{:02}:{:02}", total_secs / 60, total_secs % 60));
-                        });
-                    });
-                });
+    // Live spectrum of the audio around the playhead, with held peaks drawn as thin lines
+    fn render_spectrum_bars(&mut self, ui: &mut egui::Ui) {
+        let block = if self.player.is_playing() {
+            self.waveform.block_at(self.player.progress().as_secs_f32(), ANALYZER_SIZE)
+        } else {
+            None
+        };
+        let elapsed = ui.input(|i| i.stable_dt).min(0.1);
+        self.spectrum_analyzer.update(block, self.waveform.samples_per_second(), elapsed);
+
+        let (rect, response) = ui.allocate_exact_size(Vec2::new(160.0, COVER_ART_SIZE), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+        let levels: Vec<(f32, f32)> = self.spectrum_analyzer.levels().collect();
+        let bar_width = rect.width() / levels.len() as f32;
+        let color = self.waveform_color();
+        for (index, (level, peak)) in levels.into_iter().enumerate() {
+            let left = rect.left() + index as f32 * bar_width;
+            let bar = Rect::from_min_max(
+                egui::pos2(left + 1.0, rect.bottom() - level * rect.height()),
+                egui::pos2(left + bar_width - 1.0, rect.bottom()),
+            );
+            painter.rect_filled(bar, 0.0, color);
+            let peak_y = rect.bottom() - peak * rect.height();
+            painter.hline(bar.x_range(), peak_y, Stroke::new(1.0, Color32::WHITE));
+        }
+        response.on_hover_text("Spectrum");
+    }
 
-                ui.with_layout(Layout::centered_and_justified(egui::Direction::LeftToRight), |ui| {
-                    ui.horizontal(|ui| {
-                        let total_button_width = 3.0 * 40.0;
-                        let available_width = ui.available_width();
-                        let spacing = (available_width - total_button_width) / 3.0;
+    // Cover art, title and artist / album line of the current track
+    fn render_track_info(&self, ui: &mut egui::Ui) {
+        let Some(file) = self.player.current_file() else {
+            return;
+        };
 
-                        if spacing > 0.0 {
-                            ui.add_space(spacing);
-                        }
+        let cover_size = Vec2::splat(COVER_ART_SIZE);
+        match &self.cover_texture {
+            Some((cover_file, texture)) if cover_file == file => {
+                ui.add(egui::Image::new(texture).fit_to_exact_size(cover_size));
+            }
+            _ => {
+                let (rect, _) = ui.allocate_exact_size(cover_size, egui::Sense::hover());
+                ui.painter().rect_filled(rect, 4.0, Color32::from_gray(50));
+                ui.painter().text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    "♪",
+                    egui::FontId::proportional(20.0),
+                    Color32::GRAY,
+                );
+            }
+        }
 
-                        let play_response = AudioPlayerApp::styled_icon_button(ui, "Play", "▶");
-                        if play_response.clicked() {
-                            let file_path = self.player.current_file().map(ToOwned::to_owned);
-                            if let Some(file_path) = file_path {
-                                // If a file was paused, resume it
-                                if self.player.is_paused() {
-                                    if let Err(e) = self.player.resume() {
-                                        eprintln!("Failed to resume playback: {}", e);
-                                    }
+        ui.vertical(|ui| {
+            let metadata = &self.track_metadata;
+            ui.label(egui::RichText::new(metadata.display_title(Path::new(file))).strong().size(16.0));
+
+            let album = match (&metadata.album, &metadata.year) {
+                (Some(album), Some(year)) => Some(format!("{} ({})", album, year)),
+                (Some(album), None) => Some(album.clone()),
+                (None, Some(year)) => Some(year.clone()),
+                (None, None) => None,
+            };
+            let details: Vec<String> = metadata.artist.iter().cloned().chain(album).collect();
+            if !details.is_empty() {
+                ui.label(egui::RichText::new(details.join(" — ")).color(Color32::GRAY));
+            }
+        });
+    }
 
-                                } else {
-                                    // Otherwise play the current file again
-                                    self.play_file(&file_path);
-                                }
-                            }
-                        }
+    // Draws the whole track as a thin strip with the playhead; clicking it seeks
+    fn render_waveform_overview(&mut self, ui: &mut egui::Ui) {
+        let (rect, response) =
+            ui.allocate_exact_size(Vec2::new(ui.available_width(), OVERVIEW_HEIGHT), egui::Sense::click());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+        let buffer_len = self.waveform.get_buffer().len();
+        let buckets = rect.width().max(1.0) as usize;
+        let generating = self.waveform.is_generating();
+        if self.overview_cache.0 != buffer_len
+            || self.overview_cache.1 != buckets
+            || self.overview_cache.3 != generating
+        {
+            self.overview_cache = (buffer_len, buckets, self.waveform.get_overview(buckets), generating);
+        }
 
-                        let pause_response = AudioPlayerApp::styled_icon_button(ui, "Pause", "⏸");
-                        if pause_response.clicked() {
-                            self.player.pause();
-                        }
+        // While the waveform is still streaming in, the decoded part covers only its share of the track
+        let decoded_secs = self.waveform.overview_secs();
+        let track_secs = if self.duration_known {
+            self.total_duration.as_secs_f32().max(decoded_secs)
+        } else {
+            decoded_secs
+        };
+        if track_secs <= 0.0 {
+            return;
+        }
 
-                        let stop_response = AudioPlayerApp::styled_icon_button(ui, "Stop", "⏹");
-                        if stop_response.clicked() {
-                            self.player.stop();
-                        }
+        let decoded_rect = Rect::from_min_size(
+            rect.min,
+            Vec2::new(rect.width() * decoded_secs / track_secs, rect.height()),
+        );
+        let gain = self.waveform_display_gain();
+        let peaks: Vec<(f32, f32)> = self.overview_cache.2.iter().map(|&(min, max)| (min * gain, max * gain)).collect();
+        WaveformOverview::new(&peaks)
+            .with_color(self.waveform_color().gamma_multiply(0.8))
+            .paint(&painter, decoded_rect);
+
+        let playhead_x = rect.left() + rect.width() * self.player.progress().as_secs_f32() / track_secs;
+        painter.vline(playhead_x, rect.y_range(), Stroke::new(1.5, ACCENT_COLOR));
+
+        if response.clicked()
+            && let Some(pointer) = response.interact_pointer_pos()
+        {
+            let fraction = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            if let Err(err) = self.player.seek(Duration::from_secs_f32(fraction * track_secs)) {
+                self.last_error = Some(format!("Could not seek: {}", err));
+            }
+        }
+    }
 
-                    });
-                });
+    fn render_waveform(&mut self, ui: &mut egui::Ui) {
+        let waveform_rect = ui.available_rect_before_wrap();
+        let drag_response = ui.interact(waveform_rect, ui.id().with("waveform_pan"), egui::Sense::click_and_drag());
 
-            });
-        });
-    }
+        // In free-scroll mode a plain drag pans; Shift+drag (or any drag while following) selects
+        let panning = !self.follow_playhead && !ui.input(|i| i.modifiers.shift);
+        if panning && drag_response.dragged() {
+            let seconds_per_pixel = WAVEFORM_WINDOW_SECS / waveform_rect.width().max(1.0);
+            self.waveform_offset = (self.waveform_offset - drag_response.drag_delta().x * seconds_per_pixel).max(0.0);
+        }
 
+        let center_secs = if self.follow_playhead {
+            self.player.progress().as_secs_f32()
+        } else {
+            self.waveform_offset
+        };
 
-    fn render_waveform(&self, ui: &mut egui::Ui) {
-        let progress_secs = self.player.progress().as_secs_f32();
         let waveform_buffer = self.waveform.get_buffer();
         let waveform_len = waveform_buffer.len();
-        let sample_rate = self.waveform.get_sample_rate();
+        let sample_rate = self.waveform.samples_per_second();
 
-        let samples_played = (progress_secs * sample_rate as f32) as usize;
-        let visible_length_samples = (sample_rate as usize) * 2; // Show 2 seconds of audio
+        let center_sample = (center_secs * sample_rate) as usize;
+        let visible_length_samples = (sample_rate * WAVEFORM_WINDOW_SECS) as usize;
 
-        let start_idx = samples_played.saturating_sub(visible_length_samples / 2);
+        let start_idx = center_sample.saturating_sub(visible_length_samples / 2);
         let end_idx = (start_idx + visible_length_samples).min(waveform_len);
 
         let displayed_waveform = if start_idx < end_idx && waveform_len > 0 {
@@ -255,30 +2615,157 @@ impl AudioPlayerApp {
             &[] as &[f32]
         };
 
-        let waveform_rect = ui.available_rect_before_wrap();
+        // Maps between screen x and track time for the samples currently on screen
+        let displayed_len = displayed_waveform.len().max(1) as f32;
+        let time_at = |x: f32| {
+            let fraction = ((x - waveform_rect.left()) / waveform_rect.width()).clamp(0.0, 1.0);
+            Duration::from_secs_f32((start_idx as f32 + fraction * displayed_len) / sample_rate)
+        };
+        let x_at = |time: Duration| {
+            let sample = time.as_secs_f32() * sample_rate;
+            waveform_rect.left() + (sample - start_idx as f32) / displayed_len * waveform_rect.width()
+        };
+
+        if !displayed_waveform.is_empty() && !panning {
+            if drag_response.drag_started()
+                && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+            {
+                self.selection_anchor = Some(time_at(origin.x));
+            }
+            if drag_response.dragged()
+                && let (Some(anchor), Some(pointer)) = (self.selection_anchor, drag_response.interact_pointer_pos())
+            {
+                let current = time_at(pointer.x);
+                self.selection = Some((anchor.min(current), anchor.max(current)));
+            }
+        }
+        if drag_response.drag_stopped() {
+            self.selection_anchor = None;
+        }
+        // Shift-clicking two points loops between them. A plain click seeks there, dropping the
+        // loop when the point is outside it.
+        if drag_response.clicked() {
+            let shift = ui.input(|i| i.modifiers.shift);
+            match drag_response.interact_pointer_pos() {
+                Some(pointer) if !displayed_waveform.is_empty() => {
+                    let point = time_at(pointer.x);
+                    if shift {
+                        match self.loop_point_a.take() {
+                            Some(a) if a != point => self.selection = Some((a.min(point), a.max(point))),
+                            _ => self.loop_point_a = Some(point),
+                        }
+                    } else {
+                        self.loop_point_a = None;
+                        if self.selection.is_some_and(|(start, end)| point < start || point > end) {
+                            self.selection = None;
+                        }
+                        if let Err(err) = self.player.seek(point) {
+                            self.last_error = Some(format!("Could not seek: {}", err));
+                        }
+                    }
+                }
+                _ => {
+                    self.selection = None;
+                    self.loop_point_a = None;
+                }
+            }
+        }
+
         let painter = ui.painter_at(waveform_rect);
 
         painter.rect_filled(waveform_rect, 0.0, Color32::BLACK);
 
+        let spectrogram_view = self.settings.waveform_view == WaveformView::Spectrogram;
+        if spectrogram_view && !displayed_waveform.is_empty() {
+            self.spectrogram.update(self.waveform.get_buffer(), sample_rate);
+            let (first, frames) = self.spectrogram.frames(start_idx, end_idx);
+            if !frames.is_empty() {
+                let image = spectrogram_image(frames);
+                match &mut self.spectrogram_texture {
+                    Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                    None => {
+                        self.spectrogram_texture =
+                            Some(ui.ctx().load_texture("spectrogram", image, egui::TextureOptions::LINEAR))
+                    }
+                }
+
+                let x_at_sample = |sample: usize| {
+                    waveform_rect.left() + (sample as f32 - start_idx as f32) / displayed_len * waveform_rect.width()
+                };
+                let image_rect = Rect::from_x_y_ranges(
+                    x_at_sample(first * HOP_SIZE)..=x_at_sample((first + frames.len()) * HOP_SIZE),
+                    waveform_rect.y_range(),
+                );
+                if let Some(texture) = &self.spectrogram_texture {
+                    let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                    painter.image(texture.id(), image_rect, uv, Color32::WHITE);
+                }
+            }
+        }
+
+        if let Some((start, end)) = self.selection
+            && !displayed_waveform.is_empty()
+        {
+            let left = x_at(start).max(waveform_rect.left());
+            let right = x_at(end).min(waveform_rect.right());
+            if right > left {
+                let selection_rect = Rect::from_x_y_ranges(left..=right, waveform_rect.y_range());
+                painter.rect_filled(selection_rect, 0.0, ACCENT_COLOR.gamma_multiply(0.35));
+            }
+        }
+        if let Some(a) = self.loop_point_a {
+            let x = x_at(a);
+            if waveform_rect.x_range().contains(x) {
+                painter.vline(x, waveform_rect.y_range(), Stroke::new(2.0, LIGHTER_ACCENT_COLOR));
+            }
+        }
+
         if !displayed_waveform.is_empty() {
-            let wave_height = waveform_rect.height() / 2.0;
-            let wave_width = waveform_rect.width() / displayed_waveform.len().max(1) as f32;
-            let center_y = waveform_rect.center().y;
-
-            let points: Vec<Pos2> = displayed_waveform
-                .iter()
-                .enumerate()
-                .map(|(i, sample)| {
-                    let x = waveform_rect.left_top().x + (i as f32 * wave_width);
-                    let y = center_y - (sample * wave_height);
-                    Pos2 { x, y }
-                })
-                .collect();
+            let channels = self
+                .waveform
+                .get_channel_buffers()
+                .filter(|_| self.settings.waveform_channel_mode == WaveformChannelMode::Stereo);
+            match channels {
+                // The spectrogram drawn above takes the place of the waveform
+                _ if spectrogram_view => {}
+                // Left channel in the top half, right in the bottom
+                Some((left, right)) => {
+                    let (top, bottom) = waveform_rect.split_top_bottom_at_fraction(0.5);
+                    for (samples, rect) in [(left, top), (right, bottom)] {
+                        let end = end_idx.min(samples.len());
+                        if start_idx < end {
+                            self.paint_waveform_samples(&painter, &samples[start_idx..end], rect);
+                        }
+                    }
+                    painter.hline(waveform_rect.x_range(), top.bottom(), Stroke::new(1.0, Color32::from_gray(60)));
+                }
+                None => self.paint_waveform_samples(&painter, displayed_waveform, waveform_rect),
+            }
+
+            let bookmarks = self.player.current_file().and_then(|file| self.settings.bookmarks.get(file));
+            for (position, name) in bookmarks.into_iter().flatten() {
+                let sample = (position.as_secs_f32() * sample_rate) as usize;
+                if sample < start_idx || sample >= end_idx {
+                    continue;
+                }
+
+                let x = x_at(*position);
+                painter.vline(x, waveform_rect.y_range(), Stroke::new(1.5, BOOKMARK_COLOR));
+                painter.text(
+                    egui::pos2(x + 3.0, waveform_rect.top() + 4.0),
+                    egui::Align2::LEFT_TOP,
+                    name,
+                    egui::FontId::proportional(11.0),
+                    BOOKMARK_COLOR,
+                );
+            }
 
-            painter.add(egui::Shape::line(
-                points,
-                Stroke::new(1.5, Color32::LIGHT_BLUE),
-            ));
+            // While following, the playhead maps to the center; in free scroll it moves with playback
+            let playhead = self.player.progress();
+            let playhead_sample = (playhead.as_secs_f32() * sample_rate) as usize;
+            if (start_idx..end_idx).contains(&playhead_sample) {
+                painter.vline(x_at(playhead), waveform_rect.y_range(), Stroke::new(1.5, ACCENT_COLOR));
+            }
         } else {
             painter.text(
                 waveform_rect.center(),
@@ -289,60 +2776,433 @@ impl AudioPlayerApp {
             );
         }
 
+        if self.waveform.is_generating() {
+            let status = match self.waveform.generation_progress() {
+                Some(progress) => format!("Generating waveform… {:.0}%", progress * 100.0),
+                None => "Generating waveform…".to_string(),
+            };
+            painter.text(
+                waveform_rect.left_bottom() + Vec2::new(8.0, -6.0),
+                egui::Align2::LEFT_BOTTOM,
+                status,
+                egui::FontId::proportional(11.0),
+                Color32::GRAY,
+            );
+        }
+
+        let follow_rect = Rect::from_min_size(
+            waveform_rect.right_top() + Vec2::new(-118.0, 6.0),
+            Vec2::new(112.0, 20.0),
+        );
+        let mut follow_ui = ui.new_child(egui::UiBuilder::new().max_rect(follow_rect));
+        if follow_ui.selectable_label(self.follow_playhead, "Follow playhead").clicked() {
+            self.follow_playhead = !self.follow_playhead;
+            self.waveform_offset = self.player.progress().as_secs_f32();
+        }
+
+        let view_rect = follow_rect.translate(Vec2::new(-follow_rect.width() - 6.0, 0.0));
+        let mut view_ui = ui.new_child(egui::UiBuilder::new().max_rect(view_rect));
+        if view_ui.selectable_label(spectrogram_view, "Spectrogram").clicked() {
+            self.settings.waveform_view = if spectrogram_view {
+                WaveformView::Waveform
+            } else {
+                WaveformView::Spectrogram
+            };
+        }
+
         ui.add_space(waveform_rect.height() + 10.0);
     }
 
+    // Factor applied to drawn samples: 1 for absolute levels, or enough to lift the track's peak
+    // to full scale when normalizing
+    fn waveform_display_gain(&self) -> f32 {
+        let peak = self.waveform.peak();
+        if self.settings.normalize_waveform && peak > 1e-4 { 1.0 / peak } else { 1.0 }
+    }
+
+    fn paint_waveform_samples(&self, painter: &egui::Painter, samples: &[f32], rect: Rect) {
+        let gain = self.waveform_display_gain();
+        let scaled: Vec<f32>;
+        let samples = if gain != 1.0 {
+            scaled = samples.iter().map(|sample| sample * gain).collect();
+            &scaled[..]
+        } else {
+            samples
+        };
+        let samples = self.waveform.display_samples(samples);
+        WaveformVisualizer::new(&samples)
+            .with_color(self.waveform_color())
+            .with_style(self.settings.waveform_style)
+            .with_rectified(self.settings.rectified_waveform)
+            .paint(painter, rect);
+    }
+
     fn scan_audio_files(&mut self) {
         if let Some(dir) = &self.directory {
-            self.audio_files = AudioFileScanner::scan_directory(dir, 3);
-            self.audio_files.sort();
+            self.scan_receiver = Some(AudioFileScanner::scan_in_background(dir, SCAN_DEPTH, self.settings.scan_filter()));
+            self.directory_watcher = Some(DirectoryWatcher::watch(dir, SCAN_DEPTH, self.settings.scan_filter()));
+            self.audio_files.clear();
+            self.entry_titles.clear();
+            self.played_indices.clear();
+            // Stops the worker probing the old list
+            self.list_duration_receiver = None;
+        }
+    }
+
+    // Replaces the list with a playlist's local entries, in playlist order. Stream URLs are
+    // skipped since only local files can be played.
+    fn open_playlist(&mut self, path: &Path) {
+        let entries = match load_playlist(path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.last_error = Some(format!("Could not read playlist {}: {}", path.display(), err));
+                return;
+            }
+        };
+
+        self.scan_receiver = None;
+        self.directory_watcher = None;
+        self.audio_files.clear();
+        self.entry_titles.clear();
+        self.played_indices.clear();
+        self.list_duration_receiver = None;
+        let mut skipped = 0;
+        for entry in entries {
+            match entry.location {
+                PlaylistLocation::File(file)
+                    if AudioFileScanner::is_supported(&file, &self.settings.audio_extensions) =>
+                {
+                    let file = file.display().to_string();
+                    if let Some(title) = entry.title {
+                        self.entry_titles.insert(file.clone(), title);
+                    }
+                    self.audio_files.push(file);
+                }
+                PlaylistLocation::File(_) | PlaylistLocation::Url(_) => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            self.last_error =
+                Some(format!("Skipped {} playlist entries that are streams or unsupported files", skipped));
+        }
+        self.missing_checked = None;
+    }
+
+    // Picks up the result of a background scan once it is ready
+    fn poll_scan(&mut self) {
+        let Some(receiver) = &self.scan_receiver else {
+            return;
+        };
+
+        // Files are listed as they're found, keeping a name-sorted list sorted as it grows
+        let found = self.audio_files.len();
+        let mut finished = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(file) => self.audio_files.push(file),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        if self.audio_files.len() > found {
+            // Date and size sorts stat every file, so they wait for the scan to finish
+            if self.settings.file_sort == SortKey::Name {
+                SortKey::Name.sort(&mut self.audio_files);
+            }
+            self.played_indices.clear();
+            self.missing_checked = None;
+        }
+        if finished {
+            self.scan_receiver = None;
+            if self.settings.file_sort != SortKey::Name {
+                self.settings.file_sort.sort(&mut self.audio_files);
+                self.played_indices.clear();
+            }
+        }
+    }
+
+    fn refresh_missing_files(&mut self) {
+        if self.missing_checked.is_some_and(|checked| checked.elapsed() < MISSING_CHECK_INTERVAL) {
+            return;
+        }
+
+        self.missing_files = self
+            .audio_files
+            .iter()
+            .chain(&self.queue)
+            .filter(|file| !Path::new(file).exists())
+            .cloned()
+            .collect();
+        self.missing_checked = Some(Instant::now());
+        let missing = &self.missing_files;
+        self.selected_files.retain(|file| !missing.contains(file));
+    }
+
+    // Drops a file that disappeared from disk from the list and queue
+    fn forget_file(&mut self, file: &str) {
+        self.played_indices.clear();
+        self.audio_files.retain(|f| f != file);
+        self.queue.retain(|f| f != file);
+        self.missing_files.remove(file);
+    }
+
+    fn remove_missing_files(&mut self) {
+        self.played_indices.clear();
+        self.audio_files.retain(|file| !self.missing_files.contains(file));
+        self.queue.retain(|file| !self.missing_files.contains(file));
+        self.missing_files.clear();
+    }
+
+    // Adds a file to the queue, either at the front or the back. Unsupported paths are rejected
+    // here so nothing unplayable sits in the queue.
+    fn enqueue(&mut self, file_path: String, play_next: bool) {
+        if !AudioFileScanner::is_supported(Path::new(&file_path), &self.settings.audio_extensions) {
+            self.last_error = Some(format!("{} is not a supported audio file", Self::display_name(&file_path)));
+            return;
+        }
+        if play_next {
+            self.queue.insert(0, file_path);
+        } else {
+            self.queue.push(file_path);
         }
     }
 
+    // Color for the current track's waveform, falling back to the fixed color until the chosen
+    // metric is known or when the track has no genre tag
+    fn waveform_color(&self) -> Color32 {
+        match self.settings.waveform_color_mode {
+            WaveformColorMode::Fixed => None,
+            WaveformColorMode::Loudness => self.waveform.get_loudness_db().map(loudness_color),
+            WaveformColorMode::Genre => self.waveform.get_genre().map(genre_color),
+        }
+        .unwrap_or(WAVEFORM_COLOR)
+    }
+
     fn play_file(&mut self, file_path: &str) {
+        // Keep the place in the track being switched away from
+        self.remember_position();
+
+        if !self.start_playback(file_path) {
+            return;
+        }
+
+        self.resume_offer = None;
+        if let Some(&position) = self.settings.saved_positions.get(file_path) {
+            if self.settings.auto_resume_position {
+                if let Err(err) = self.player.seek(position) {
+                    self.last_error = Some(format!("Could not restore the playback position: {}", err));
+                }
+            } else {
+                self.resume_offer = Some((file_path.to_string(), position));
+            }
+        }
+    }
+
+    // Starts a track from the top with its own gain, reporting failures. Returns whether it plays.
+    fn start_playback(&mut self, file_path: &str) -> bool {
+        if !Path::new(file_path).exists() {
+            self.last_error = Some(format!("{} no longer exists", Self::display_name(file_path)));
+            self.forget_file(file_path);
+            return false;
+        }
+
+        let track_gain = self.settings.track_gains.get(file_path).copied().unwrap_or(0.0);
+        self.player.set_track_gain_db(track_gain);
+        self.player.set_match_gain_db(0.0);
+        self.player.set_replay_gain_db(0.0);
+        self.preview = None;
+
         if let Err(err) = self.player.play(file_path) {
-            eprintln!("Error playing file: {}", err);
+            self.last_error = Some(format!("Could not play {}: {}", Self::display_name(file_path), err));
+            return false;
+        }
+        self.track_started(file_path);
+        true
+    }
+
+    // Records where the playing track is, or forgets it when it's near the start or end
+    fn remember_position(&mut self) {
+        let Some(file) = self.player.current_file().map(ToOwned::to_owned) else {
+            return;
+        };
+        if !self.duration_known || self.player.is_finished() {
             return;
         }
 
+        let position = self.player.progress();
+        if position >= RESUME_MARGIN && position + RESUME_MARGIN < self.total_duration {
+            self.settings.saved_positions.insert(file, position);
+        } else {
+            self.settings.saved_positions.remove(&file);
+        }
+    }
+
+    fn save_position_periodically(&mut self) {
+        if !self.player.is_playing() {
+            return;
+        }
+        if self.position_saved_at.is_none_or(|saved| saved.elapsed() >= POSITION_SAVE_INTERVAL) {
+            self.remember_position();
+            self.position_saved_at = Some(Instant::now());
+        }
+    }
+
+    fn render_resume_offer(&mut self, ui: &mut egui::Ui) {
+        let Some((file, position)) = self.resume_offer.clone() else {
+            return;
+        };
+        if self.player.current_file() != Some(file.as_str()) {
+            self.resume_offer = None;
+            return;
+        }
+
+        let mut resume = false;
+        let mut dismiss = false;
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            resume = ui.button(format!("Resume from {}", format_duration(position))).clicked();
+            ui.checkbox(&mut self.settings.auto_resume_position, "Always resume");
+            let dismiss_response = ui.small_button("✖");
+            Self::accessible_label(&dismiss_response, egui::WidgetType::Button, "Dismiss");
+            dismiss = dismiss_response.clicked();
+        });
+
+        if resume {
+            if let Err(err) = self.player.seek(position) {
+                self.last_error = Some(format!("Could not seek: {}", err));
+            }
+            self.resume_offer = None;
+        } else if dismiss {
+            self.resume_offer = None;
+        }
+    }
+
+    // Resets per-track state and starts the background analysis for a track that just began
+    fn track_started(&mut self, file_path: &str) {
+        self.last_error = None;
+
         self.waveform.generate_for(file_path);
+        self.spectrogram.clear();
+        self.follow_playhead = true;
+        self.selection = None;
+        self.loop_point_a = None;
+        self.loops_remaining = self.loop_count.saturating_sub(1);
+
+        self.playing_file_stamp = Self::file_stamp(file_path);
+        self.playback_error = None;
+
+        // The waveform worker reports the length from its own probe of the file
+        self.total_duration = Duration::ZERO;
+        self.duration_known = false;
+
+        self.track_metadata = TrackMetadata::default();
+        if self.cover_texture.as_ref().is_some_and(|(cover_file, _)| cover_file != file_path) {
+            self.cover_texture = None;
+        }
+        let (tx, rx) = channel();
+        let path = PathBuf::from(file_path);
+        thread::spawn(move || {
+            let _ = tx.send(read_metadata(&path));
+        });
+        self.metadata_receiver = Some(rx);
+    }
 
-        match self.get_audio_duration(file_path) {
-            Ok(duration) => self.total_duration = duration,
-            Err(_) => self.total_duration = Duration::from_secs(180),
+    fn poll_metadata(&mut self, ctx: &Context) {
+        let Some(receiver) = &self.metadata_receiver else {
+            return;
+        };
+
+        match receiver.try_recv() {
+            Ok(mut metadata) => {
+                let file = self.player.current_file().unwrap_or_default().to_string();
+                let cached = self.cover_texture.as_ref().is_some_and(|(cover_file, _)| *cover_file == file);
+                if let Some(cover) = metadata.cover.take()
+                    && !cached
+                {
+                    let image = egui::ColorImage::from_rgba_unmultiplied(cover.size, &cover.rgba);
+                    let texture = ctx.load_texture("cover_art", image, egui::TextureOptions::LINEAR);
+                    self.cover_texture = Some((file, texture));
+                }
+                self.track_metadata = metadata;
+                self.metadata_receiver = None;
+                self.apply_replay_gain();
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.metadata_receiver = None,
         }
     }
 
-    fn get_audio_duration(&self, file_path: &str) -> Result<Duration, Box<dyn std::error::Error>> {
-        let file = File::open(file_path)?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    // Applies the current track's ReplayGain tag when normalization is on. Untagged tracks play
+    // unadjusted since their loudness isn't known until the whole file has been decoded.
+    fn apply_replay_gain(&mut self) {
+        let gain_db = if self.settings.replay_gain { self.track_metadata.replay_gain_db.unwrap_or(0.0) } else { 0.0 };
+        self.player.set_replay_gain_db(gain_db);
+    }
 
-        let mut hint = Hint::new();
-        if let Some(extension) = Path::new(file_path).extension() {
-            hint.with_extension(&extension.to_string_lossy());
+    // Collects lengths probed in the background, and starts a worker for listed files that
+    // haven't been probed yet once the previous one is done
+    fn poll_list_durations(&mut self) {
+        if let Some(receiver) = &self.list_duration_receiver {
+            loop {
+                match receiver.try_recv() {
+                    Ok((file, duration)) => {
+                        self.list_durations.insert(file, duration);
+                    }
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+            self.list_duration_receiver = None;
         }
 
-        let format_opts = FormatOptions::default();
-        let metadata_opts = MetadataOptions::default();
+        // Wait for the scan so the worker gets the whole list at once
+        if self.scan_receiver.is_some() {
+            return;
+        }
+        let pending: Vec<String> =
+            self.audio_files.iter().filter(|file| !self.list_durations.contains_key(*file)).cloned().collect();
+        if pending.is_empty() {
+            return;
+        }
 
-        let probed = symphonia::default::get_probe()
-            .format(&hint, mss, &format_opts, &metadata_opts)?;
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for file in pending {
+                let duration = probe_duration(Path::new(&file)).ok();
+                if tx.send((file, duration)).is_err() {
+                    break;
+                }
+            }
+        });
+        self.list_duration_receiver = Some(rx);
+    }
 
-        if let Some(track) = probed.format.tracks().get(0) {
-            if let (Some(n_frames), Some(sample_rate)) = (
-                track.codec_params.n_frames,
-                track.codec_params.sample_rate,
-            ) {
-                let seconds = n_frames as f64 / sample_rate as f64;
-                return Ok(Duration::from_secs_f64(seconds));
+    // "N tracks • H:MM:SS" for the listed files, with the length shown once every file is probed
+    fn list_summary(&self) -> String {
+        let count = self.audio_files.len();
+        let tracks = if count == 1 { "1 track".to_string() } else { format!("{} tracks", count) };
+
+        let durations: Option<Vec<Option<Duration>>> =
+            self.audio_files.iter().map(|file| self.list_durations.get(file).copied()).collect();
+        match durations {
+            Some(durations) => {
+                let total: Duration = durations.into_iter().flatten().sum();
+                format!("{} • {}", tracks, format_duration(total))
             }
+            None => format!("{} • calculating…", tracks),
         }
+    }
 
-        Err("Could not determine audio duration".into())
+    fn file_stamp(file_path: &str) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(file_path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
     }
 
     fn styled_icon_button(ui: &mut egui::Ui, label: &str, icon: &str) -> egui::Response {
-        ui.add_sized(
+        let response = ui.add_sized(
             egui::vec2(90.0, 30.0),
             egui::Button::new(
                 egui::RichText::new(format!("{} {}", icon, label))
@@ -350,8 +3210,30 @@ impl AudioPlayerApp {
                     .size(14.0),
             )
                 .fill(ACCENT_COLOR)
-                .rounding(egui::Rounding::same(4))
+                .corner_radius(egui::CornerRadius::same(4))
                 .frame(true),
-        )
+        );
+        // Announce just the label, not the icon glyph
+        Self::accessible_label(&response, egui::WidgetType::Button, label);
+        Self::paint_focus_ring(ui, &response);
+        response
+    }
+
+    // Gives a widget a screen reader name, for widgets whose visible text is an icon or absent
+    fn accessible_label(response: &egui::Response, typ: egui::WidgetType, label: &str) {
+        response.widget_info(|| egui::WidgetInfo::labeled(typ, response.enabled(), label));
+    }
+
+    // Outlines the widget with keyboard focus. Custom-filled widgets otherwise look identical
+    // whether focused or not.
+    fn paint_focus_ring(ui: &egui::Ui, response: &egui::Response) {
+        if response.has_focus() {
+            ui.painter().rect_stroke(
+                response.rect.expand(2.0),
+                4.0,
+                Stroke::new(2.0, Color32::WHITE),
+                egui::StrokeKind::Outside,
+            );
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use rodio::Sink;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Interval between volume updates while a crossfade is running
+const RAMP_STEP: Duration = Duration::from_millis(10);
+
+// Shape of the gain ramps applied to the outgoing and incoming tracks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossfadeCurve {
+    #[default]
+    Linear,
+    // Sine/cosine ramps keep the summed power constant, avoiding the mid-point loudness dip
+    EqualPower,
+}
+
+impl CrossfadeCurve {
+    pub const ALL: [CrossfadeCurve; 2] = [Self::Linear, Self::EqualPower];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::EqualPower => "Equal power",
+        }
+    }
+
+    // Returns the (outgoing, incoming) gains for crossfade progress `t` in [0, 1]
+    pub fn gains(self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => (1.0 - t, t),
+            Self::EqualPower => ((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin()),
+        }
+    }
+}
+
+// Ramps a sink's volume down to silence on a short-lived thread, then stops it
+pub fn spawn_fade_out(sink: Arc<Mutex<Sink>>, duration: Duration) {
+    let start_volume = sink.lock().unwrap().volume();
+
+    thread::spawn(move || {
+        let start = Instant::now();
+        loop {
+            let t = (start.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0);
+            sink.lock().unwrap().set_volume(start_volume * (1.0 - t));
+
+            if t >= 1.0 {
+                break;
+            }
+            thread::sleep(RAMP_STEP);
+        }
+        sink.lock().unwrap().stop();
+    });
+}
+
+// Ramps `outgoing` down and `incoming` up to `target_volume` on a short-lived thread,
+// stopping the outgoing sink once the fade completes
+pub fn spawn_crossfade(
+    outgoing: Arc<Mutex<Sink>>,
+    incoming: Arc<Mutex<Sink>>,
+    duration: Duration,
+    curve: CrossfadeCurve,
+    target_volume: f32,
+) {
+    let outgoing_volume = outgoing.lock().unwrap().volume();
+    incoming.lock().unwrap().set_volume(0.0);
+
+    thread::spawn(move || {
+        let start = Instant::now();
+        loop {
+            let t = start.elapsed().as_secs_f32() / duration.as_secs_f32();
+            let (out_gain, in_gain) = curve.gains(t);
+            outgoing.lock().unwrap().set_volume(outgoing_volume * out_gain);
+            incoming.lock().unwrap().set_volume(target_volume * in_gain);
+
+            if t >= 1.0 {
+                break;
+            }
+            thread::sleep(RAMP_STEP);
+        }
+        outgoing.lock().unwrap().stop();
+    });
+}
\ No newline at end of file
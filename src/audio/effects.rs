@@ -0,0 +1,767 @@
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How long the compressor takes to blend in or out when toggled, so engaging it never clicks
+const ENGAGE_RAMP_SECS: f32 = 0.02;
+
+// How long the mono sum takes to blend in or out
+const MONO_RAMP_SECS: f32 = 0.01;
+
+// Time constant for gain changes; short enough to feel immediate, long enough to avoid zipper noise
+const GAIN_SMOOTHING_SECS: f32 = 0.005;
+
+// Length of the grains the pitch shifter crossfades between. Longer grains smear transients,
+// shorter ones sound rougher on low notes.
+const PITCH_GRAIN_SECS: f32 = 0.04;
+
+// Number of samples between re-reads of the shared compressor parameters
+const PARAM_REFRESH_SAMPLES: usize = 1024;
+
+// Highest level the compressor lets out. Peaks the slower envelope misses are caught by a limiter
+// that turns the gain down for them instead of being clipped.
+const LIMITER_CEILING: f32 = 0.97;
+
+// How quickly the limiter hands the gain back after a peak
+const LIMITER_RELEASE_MS: f32 = 80.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompressorSettings {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        CompressorPreset::Night.settings()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressorPreset {
+    Gentle,
+    #[default]
+    Night,
+    Heavy,
+}
+
+impl CompressorPreset {
+    pub const ALL: [CompressorPreset; 3] = [Self::Gentle, Self::Night, Self::Heavy];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Gentle => "Gentle",
+            Self::Night => "Night",
+            Self::Heavy => "Heavy",
+        }
+    }
+
+    pub fn settings(self) -> CompressorSettings {
+        match self {
+            Self::Gentle => CompressorSettings {
+                threshold_db: -18.0,
+                ratio: 2.0,
+                attack_ms: 20.0,
+                release_ms: 250.0,
+                makeup_db: 3.0,
+            },
+            Self::Night => CompressorSettings {
+                threshold_db: -24.0,
+                ratio: 4.0,
+                attack_ms: 10.0,
+                release_ms: 300.0,
+                makeup_db: 6.0,
+            },
+            Self::Heavy => CompressorSettings {
+                threshold_db: -32.0,
+                ratio: 8.0,
+                attack_ms: 5.0,
+                release_ms: 400.0,
+                makeup_db: 8.0,
+            },
+        }
+    }
+}
+
+// Shared handle used by the UI to toggle and tune a compressor that lives inside the sink
+#[derive(Clone, Default)]
+pub struct CompressorControl {
+    enabled: Arc<AtomicBool>,
+    settings: Arc<Mutex<CompressorSettings>>,
+}
+
+impl CompressorControl {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_settings(&self, settings: CompressorSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+}
+
+// Feed-forward peak compressor applied as a rodio source adapter
+pub struct Compressor<S> {
+    input: S,
+    control: CompressorControl,
+    settings: CompressorSettings,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    limiter_release_coefficient: f32,
+    envelope: f32,
+    // Peak of the made-up signal, jumping up instantly and falling back at the limiter release
+    limiter_envelope: f32,
+    mix: f32,
+    samples_until_refresh: usize,
+}
+
+impl<S> Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: CompressorControl) -> Self {
+        let settings = *control.settings.lock().unwrap();
+        let mix = if control.is_enabled() { 1.0 } else { 0.0 };
+        let mut compressor = Self {
+            input,
+            control,
+            settings,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+            limiter_release_coefficient: 0.0,
+            envelope: 0.0,
+            limiter_envelope: 0.0,
+            mix,
+            samples_until_refresh: PARAM_REFRESH_SAMPLES,
+        };
+        compressor.update_coefficients();
+        compressor
+    }
+
+    fn update_coefficients(&mut self) {
+        self.attack_coefficient = self.coefficient(self.settings.attack_ms);
+        self.release_coefficient = self.coefficient(self.settings.release_ms);
+        self.limiter_release_coefficient = self.coefficient(LIMITER_RELEASE_MS);
+    }
+
+    // Samples per second across all channels, used to turn time constants into per-sample coefficients
+    fn samples_per_sec(&self) -> f32 {
+        (self.input.sample_rate() as f32 * self.input.channels().max(1) as f32).max(1.0)
+    }
+
+    fn coefficient(&self, time_ms: f32) -> f32 {
+        let samples = (time_ms / 1000.0 * self.samples_per_sec()).max(1.0);
+        (-1.0 / samples).exp()
+    }
+
+    fn gain_for_envelope(&self) -> f32 {
+        let level_db = 20.0 * self.envelope.max(1e-6).log10();
+        let over_db = level_db - self.settings.threshold_db;
+        let reduction_db = if over_db > 0.0 {
+            over_db * (1.0 - 1.0 / self.settings.ratio.max(1.0))
+        } else {
+            0.0
+        };
+        10f32.powf((self.settings.makeup_db - reduction_db) / 20.0)
+    }
+
+    // Scales a sample down as far as needed to keep it under the ceiling
+    fn limit(&mut self, sample: f32) -> f32 {
+        self.limiter_envelope = sample.abs().max(self.limiter_release_coefficient * self.limiter_envelope);
+        if self.limiter_envelope > LIMITER_CEILING {
+            sample * LIMITER_CEILING / self.limiter_envelope
+        } else {
+            sample
+        }
+    }
+}
+
+impl<S> Iterator for Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+
+        self.samples_until_refresh -= 1;
+        if self.samples_until_refresh == 0 {
+            self.samples_until_refresh = PARAM_REFRESH_SAMPLES;
+            if let Ok(settings) = self.control.settings.try_lock() {
+                self.settings = *settings;
+            }
+            self.update_coefficients();
+        }
+
+        // Keep the detector running while bypassed so engaging starts from a settled envelope
+        let level = sample.abs();
+        let coefficient = if level > self.envelope {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.envelope = coefficient * self.envelope + (1.0 - coefficient) * level;
+
+        let target_mix = if self.control.is_enabled() { 1.0 } else { 0.0 };
+        if self.mix != target_mix {
+            let step = 1.0 / (ENGAGE_RAMP_SECS * self.samples_per_sec());
+            self.mix = if target_mix > self.mix {
+                (self.mix + step).min(target_mix)
+            } else {
+                (self.mix - step).max(target_mix)
+            };
+        }
+
+        if self.mix == 0.0 {
+            self.limiter_envelope = 0.0;
+            return Some(sample);
+        }
+
+        let gain = 1.0 - self.mix + self.mix * self.gain_for_envelope();
+        Some(self.limit(sample * gain))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+// Shared handle for momentarily folding the output down to mono
+#[derive(Clone, Default)]
+pub struct MonoSumControl {
+    enabled: Arc<AtomicBool>,
+}
+
+impl MonoSumControl {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+}
+
+// Sums all channels of each frame to their mean, ramping in and out to avoid clicks
+pub struct MonoSum<S> {
+    input: S,
+    control: MonoSumControl,
+    frame: Vec<f32>,
+    position: usize,
+    mix: f32,
+}
+
+impl<S> MonoSum<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: MonoSumControl) -> Self {
+        let mix = if control.is_enabled() { 1.0 } else { 0.0 };
+        Self {
+            input,
+            control,
+            frame: Vec::new(),
+            position: 0,
+            mix,
+        }
+    }
+
+    // Reads the next interleaved frame and blends it toward its mono sum
+    fn fill_frame(&mut self) {
+        let channels = self.input.channels().max(1) as usize;
+        self.frame.clear();
+        self.position = 0;
+        while self.frame.len() < channels {
+            match self.input.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+
+        let target_mix = if self.control.is_enabled() { 1.0 } else { 0.0 };
+        if self.mix != target_mix {
+            let step = 1.0 / (MONO_RAMP_SECS * self.input.sample_rate().max(1) as f32);
+            self.mix = if target_mix > self.mix {
+                (self.mix + step).min(target_mix)
+            } else {
+                (self.mix - step).max(target_mix)
+            };
+        }
+
+        if self.mix > 0.0 && self.frame.len() > 1 {
+            let mean = self.frame.iter().sum::<f32>() / self.frame.len() as f32;
+            for sample in &mut self.frame {
+                *sample = *sample * (1.0 - self.mix) + mean * self.mix;
+            }
+        }
+    }
+}
+
+impl<S> Iterator for MonoSum<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.frame.len() {
+            self.fill_frame();
+        }
+
+        let sample = *self.frame.get(self.position)?;
+        self.position += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for MonoSum<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.frame.clear();
+        self.position = 0;
+        self.input.try_seek(pos)
+    }
+}
+
+// Target linear gain shared with a `SmoothGain`, stored as f32 bits so the audio thread never locks
+#[derive(Clone)]
+pub struct GainControl {
+    target: Arc<AtomicU32>,
+}
+
+impl GainControl {
+    pub fn new(gain: f32) -> Self {
+        Self {
+            target: Arc::new(AtomicU32::new(gain.to_bits())),
+        }
+    }
+
+    pub fn set_target(&self, gain: f32) {
+        self.target.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn target(&self) -> f32 {
+        f32::from_bits(self.target.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for GainControl {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+// Applies a gain that glides toward its target instead of jumping, settling exactly on it
+pub struct SmoothGain<S> {
+    input: S,
+    control: GainControl,
+    gain: f32,
+}
+
+impl<S> SmoothGain<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: GainControl) -> Self {
+        let gain = control.target();
+        Self { input, control, gain }
+    }
+}
+
+impl<S> Iterator for SmoothGain<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+
+        let target = self.control.target();
+        if self.gain != target {
+            let rate = self.input.sample_rate().max(1) as f32 * self.input.channels().max(1) as f32;
+            let coeff = 1.0 - (-1.0 / (GAIN_SMOOTHING_SECS * rate)).exp();
+            self.gain += (target - self.gain) * coeff;
+            if (target - self.gain).abs() < 1e-4 {
+                self.gain = target;
+            }
+        }
+
+        Some(sample * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for SmoothGain<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+// Position within the track shared with a `PositionTracker`, in nanoseconds of track time
+#[derive(Clone, Default)]
+pub struct PositionControl {
+    nanos: Arc<AtomicU64>,
+}
+
+impl PositionControl {
+    pub fn position(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+// Counts how much of the track a source has produced. The sink's own position is measured after
+// its speed control, in playback time, so it drifts from the track once the speed changes.
+pub struct PositionTracker<S> {
+    input: S,
+    control: PositionControl,
+    secs: f64,
+}
+
+impl<S> PositionTracker<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: PositionControl) -> Self {
+        control.nanos.store(0, Ordering::Relaxed);
+        Self {
+            input,
+            control,
+            secs: 0.0,
+        }
+    }
+}
+
+impl<S> Iterator for PositionTracker<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        let rate = self.input.sample_rate().max(1) as f64 * self.input.channels().max(1) as f64;
+        self.secs += 1.0 / rate;
+        self.control.nanos.store((self.secs * 1e9) as u64, Ordering::Relaxed);
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for PositionTracker<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.secs = pos.as_secs_f64();
+        self.control.nanos.store(pos.as_nanos() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+// Pitch offset in semitones shared with a `PitchShift`; 0 bypasses the processing
+#[derive(Clone, Default)]
+pub struct PitchControl {
+    semitones: Arc<AtomicI32>,
+}
+
+impl PitchControl {
+    pub fn set_semitones(&self, semitones: i32) {
+        self.semitones.store(semitones.clamp(-12, 12), Ordering::Relaxed);
+    }
+
+    pub fn semitones(&self) -> i32 {
+        self.semitones.load(Ordering::Relaxed)
+    }
+}
+
+// Shifts pitch without changing tempo using two delay-line read heads that sweep through a short
+// grain at the pitch ratio, crossfaded with complementary sin² windows so the sum stays level
+pub struct PitchShift<S> {
+    input: S,
+    control: PitchControl,
+    semitones: i32,
+    // Per-channel circular history of the input
+    history: Vec<Vec<f32>>,
+    write_pos: usize,
+    grain_len: f32,
+    // Position of the first read head within the grain, 0..1; the second is half a grain behind
+    phase: f32,
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl<S> PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: PitchControl) -> Self {
+        let semitones = control.semitones();
+        let mut shift = Self {
+            input,
+            control,
+            semitones,
+            history: Vec::new(),
+            write_pos: 0,
+            grain_len: 0.0,
+            phase: 0.0,
+            frame: Vec::new(),
+            position: 0,
+        };
+        shift.reset();
+        shift
+    }
+
+    // Clears the delay lines, sizing them for the current stream format
+    fn reset(&mut self) {
+        let channels = self.input.channels().max(1) as usize;
+        self.grain_len = (PITCH_GRAIN_SECS * self.input.sample_rate().max(1) as f32).max(2.0);
+        let capacity = self.grain_len as usize + 2;
+        self.history = vec![vec![0.0; capacity]; channels];
+        self.write_pos = 0;
+        self.phase = 0.0;
+    }
+
+    // Reads the sample `delay` samples behind the write head, interpolating between neighbours
+    fn read_delayed(history: &[f32], write_pos: usize, delay: f32) -> f32 {
+        let len = history.len();
+        let position = (write_pos + len) as f32 - 1.0 - delay;
+        let index = position.floor() as usize;
+        let frac = position - position.floor();
+        let a = history[index % len];
+        let b = history[(index + 1) % len];
+        a + (b - a) * frac
+    }
+
+    fn fill_frame(&mut self) {
+        let channels = self.input.channels().max(1) as usize;
+        self.frame.clear();
+        self.position = 0;
+        while self.frame.len() < channels {
+            match self.input.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+
+        let semitones = self.control.semitones();
+        if semitones != self.semitones || self.history.len() != channels {
+            self.semitones = semitones;
+            self.reset();
+        }
+        if self.semitones == 0 || self.frame.len() < channels {
+            return;
+        }
+
+        let ratio = 2f32.powf(self.semitones as f32 / 12.0);
+        let delay_a = self.phase * self.grain_len;
+        let phase_b = (self.phase + 0.5).fract();
+        let delay_b = phase_b * self.grain_len;
+        let gain_a = (PI * self.phase).sin().powi(2);
+        let gain_b = (PI * phase_b).sin().powi(2);
+
+        for (channel, sample) in self.frame.iter_mut().enumerate() {
+            let history = &mut self.history[channel];
+            history[self.write_pos] = *sample;
+            let write_pos = self.write_pos + 1;
+            *sample = Self::read_delayed(history, write_pos, delay_a) * gain_a
+                + Self::read_delayed(history, write_pos, delay_b) * gain_b;
+        }
+
+        self.write_pos = (self.write_pos + 1) % self.history[0].len();
+        // Reading faster than writing shrinks the delay (pitch up), slower grows it (pitch down)
+        self.phase = (self.phase + (1.0 - ratio) / self.grain_len).rem_euclid(1.0);
+    }
+}
+
+impl<S> Iterator for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.frame.len() {
+            self.fill_frame();
+        }
+
+        let sample = *self.frame.get(self.position)?;
+        self.position += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.frame.clear();
+        self.position = 0;
+        self.reset();
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 44_100;
+
+    fn compress(preset: CompressorPreset, samples: Vec<f32>) -> Vec<f32> {
+        let control = CompressorControl::default();
+        control.set_enabled(true);
+        control.set_settings(preset.settings());
+        Compressor::new(SamplesBuffer::new(1, RATE, samples), control).collect()
+    }
+
+    // A second of quiet hum and then a full-scale hit, the case the slow envelope lets through
+    fn quiet_then_loud() -> Vec<f32> {
+        let quiet = (0..RATE).map(|i| 0.01 * (i as f32 * 0.05).sin());
+        let loud = (0..RATE / 10).map(|i| if i % 50 < 25 { 1.0 } else { -1.0 });
+        quiet.chain(loud).collect()
+    }
+
+    #[test]
+    fn sudden_peaks_are_limited_below_full_scale() {
+        for preset in CompressorPreset::ALL {
+            let output = compress(preset, quiet_then_loud());
+            let peak = output.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(peak <= LIMITER_CEILING + 1e-6, "{} peaks at {}", preset.label(), peak);
+        }
+    }
+
+    #[test]
+    fn quiet_material_is_left_to_the_makeup_gain() {
+        let input = quiet_then_loud();
+        let output = compress(CompressorPreset::Heavy, input.clone());
+        let makeup = 10f32.powf(CompressorPreset::Heavy.settings().makeup_db / 20.0);
+        // Past the engage ramp the hum is only made up, never limited
+        for (input, output) in input.iter().zip(&output).take(RATE as usize).skip(RATE as usize / 10) {
+            assert!((input * makeup - output).abs() < 1e-4);
+        }
+    }
+}
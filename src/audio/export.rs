@@ -0,0 +1,403 @@
+use crate::audio::flac_writer::FlacWriter;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::errors::Error;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+use symphonia::default::{get_codecs, get_probe};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Wav,
+    Flac,
+    Mp3,
+    Ogg,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [Self::Wav, Self::Flac, Self::Mp3, Self::Ogg];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Wav => "WAV",
+            Self::Flac => "FLAC",
+            Self::Mp3 => "MP3",
+            Self::Ogg => "OGG Vorbis",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Mp3 => "mp3",
+            Self::Ogg => "ogg",
+        }
+    }
+
+    // Why this build can't write the format, or None when it can
+    pub fn unavailable_reason(self) -> Option<&'static str> {
+        match self {
+            Self::Wav | Self::Flac => None,
+            Self::Mp3 => Some("MP3 export needs an encoder this build doesn't include"),
+            Self::Ogg => Some("OGG Vorbis export needs an encoder this build doesn't include"),
+        }
+    }
+
+    // Picks the format matching the extension the user typed in the save dialog
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        Self::ALL.into_iter().find(|format| format.extension() == extension)
+    }
+}
+
+// Sample size of the exported file. Sources deeper than 16 bits keep up to 24 of them;
+// everything else, including float and lossy sources, is written at 16 bits.
+fn export_bits_per_sample(source_bits: Option<u32>) -> u32 {
+    if source_bits.is_some_and(|bits| bits > 16) { 24 } else { 16 }
+}
+
+// Destination file, opened once the decoded sample format is known
+enum SampleWriter {
+    Wav(WavWriter<BufWriter<File>>),
+    Flac(FlacWriter),
+}
+
+impl SampleWriter {
+    fn create(
+        path: &Path,
+        format: ExportFormat,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u32,
+    ) -> Result<Self, String> {
+        match format {
+            ExportFormat::Wav => {
+                let wav_spec = WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample: bits_per_sample as u16,
+                    sample_format: SampleFormat::Int,
+                };
+                WavWriter::create(path, wav_spec).map(Self::Wav).map_err(|e| e.to_string())
+            }
+            ExportFormat::Flac => FlacWriter::create(path, channels, sample_rate, bits_per_sample)
+                .map(Self::Flac)
+                .map_err(|e| e.to_string()),
+            ExportFormat::Mp3 | ExportFormat::Ogg => Err(format
+                .unavailable_reason()
+                .unwrap_or("This format can't be written")
+                .to_string()),
+        }
+    }
+
+    // Writes interleaved samples already scaled to the file's sample size
+    fn write_samples(&mut self, samples: &[i32]) -> Result<(), String> {
+        match self {
+            Self::Wav(writer) => {
+                for &sample in samples {
+                    writer.write_sample(sample).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            Self::Flac(writer) => writer.write_samples(samples).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn finalize(self) -> Result<(), String> {
+        match self {
+            Self::Wav(writer) => writer.finalize().map_err(|e| e.to_string()),
+            Self::Flac(writer) => writer.finalize().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+enum ExportMsg {
+    Progress(f32),
+    Finished(Result<PathBuf, String>),
+}
+
+// A transcode running on a background thread, polled by the UI each frame
+pub struct ExportJob {
+    receiver: Receiver<ExportMsg>,
+    cancel: Arc<AtomicBool>,
+    progress: Option<f32>,
+    result: Option<Result<PathBuf, String>>,
+}
+
+impl ExportJob {
+    // Exports the whole track, or only `range` when one is given
+    pub fn start(
+        source_path: &str,
+        dest_path: PathBuf,
+        format: ExportFormat,
+        range: Option<(Duration, Duration)>,
+    ) -> Self {
+        let (tx, rx) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let source_path = source_path.to_string();
+        let cancel_flag = cancel.clone();
+        thread::spawn(move || {
+            let result = Self::transcode(&source_path, &dest_path, format, range, &cancel_flag, &tx);
+            let _ = tx.send(ExportMsg::Finished(result.map(|_| dest_path)));
+        });
+
+        Self {
+            receiver: rx,
+            cancel,
+            progress: None,
+            result: None,
+        }
+    }
+
+    // Drains pending messages from the worker
+    pub fn poll(&mut self) {
+        for msg in self.receiver.try_iter() {
+            match msg {
+                ExportMsg::Progress(progress) => self.progress = Some(progress),
+                ExportMsg::Finished(result) => self.result = Some(result),
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // Fraction of the track written so far, or None when the length is unknown
+    pub fn progress(&self) -> Option<f32> {
+        self.progress
+    }
+
+    pub fn result(&self) -> Option<&Result<PathBuf, String>> {
+        self.result.as_ref()
+    }
+
+    fn transcode(
+        source_path: &str,
+        dest_path: &Path,
+        format: ExportFormat,
+        range: Option<(Duration, Duration)>,
+        cancel: &AtomicBool,
+        tx: &Sender<ExportMsg>,
+    ) -> Result<(), String> {
+        if let Some(reason) = format.unavailable_reason() {
+            return Err(reason.to_string());
+        }
+
+        let file = File::open(source_path).map_err(|e| e.to_string())?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = Path::new(source_path).extension() {
+            hint.with_extension(&extension.to_string_lossy());
+        }
+
+        let probed = get_probe()
+            .format(&hint, mss, &Default::default(), &Default::default())
+            .map_err(|e| e.to_string())?;
+        let mut format_reader = probed.format;
+
+        let track = format_reader.default_track().ok_or("File has no audio track")?;
+        let track_id = track.id;
+        let total_frames = track.codec_params.n_frames;
+        let bits_per_sample = export_bits_per_sample(track.codec_params.bits_per_sample);
+        let mut decoder = get_codecs()
+            .make(&track.codec_params, &Default::default())
+            .map_err(|e| e.to_string())?;
+
+        let mut writer: Option<SampleWriter> = None;
+        // Decoded at full 32-bit scale, then shifted down to the exported sample size
+        let mut sample_buffer: Option<SampleBuffer<i32>> = None;
+        let mut scaled = Vec::new();
+        let mut frames_decoded = 0u64;
+        let mut frames_written = 0u64;
+        // Frame bounds of the exported range, resolved once the sample rate is known
+        let mut bounds: Option<(u64, u64)> = None;
+
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                drop(writer);
+                let _ = std::fs::remove_file(dest_path);
+                return Err("Export cancelled".to_string());
+            }
+
+            let packet = match format_reader.next_packet() {
+                Ok(p) => p,
+                Err(Error::IoError(_)) => break, // End of file
+                Err(e) => return Err(e.to_string()),
+            };
+
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            let audio_buffer = match decoder.decode(&packet) {
+                Ok(b) => b,
+                Err(Error::DecodeError(_)) => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+
+            let spec = *audio_buffer.spec();
+            let frames = audio_buffer.frames();
+            let channels = spec.channels.count();
+
+            let (first_frame, end_frame) = *bounds.get_or_insert_with(|| match range {
+                Some((start, end)) => (
+                    (start.as_secs_f64() * spec.rate as f64) as u64,
+                    (end.as_secs_f64() * spec.rate as f64) as u64,
+                ),
+                None => (0, total_frames.unwrap_or(u64::MAX)),
+            });
+
+            let buffer_start = frames_decoded;
+            frames_decoded += frames as u64;
+            if frames_decoded <= first_frame {
+                continue;
+            }
+            if buffer_start >= end_frame {
+                break;
+            }
+
+            if writer.is_none() {
+                writer = Some(SampleWriter::create(dest_path, format, channels as u16, spec.rate, bits_per_sample)?);
+            }
+
+            let buffer = sample_buffer.get_or_insert_with(|| SampleBuffer::new(audio_buffer.capacity() as u64, spec));
+            if buffer.capacity() < frames * channels {
+                *buffer = SampleBuffer::new(audio_buffer.capacity() as u64, spec);
+            }
+            buffer.copy_interleaved_ref(audio_buffer);
+
+            // Trim the parts of this buffer that fall outside the exported range
+            let skip = first_frame.saturating_sub(buffer_start) as usize;
+            let take = (end_frame.min(frames_decoded) - buffer_start) as usize;
+            let samples = &buffer.samples()[skip * channels..take * channels];
+            scaled.clear();
+            scaled.extend(samples.iter().map(|&sample| sample >> (32 - bits_per_sample)));
+
+            if let Some(writer) = writer.as_mut() {
+                writer.write_samples(&scaled)?;
+            }
+
+            frames_written += (take - skip) as u64;
+            let expected = match range {
+                Some(_) => end_frame - first_frame,
+                None => total_frames.unwrap_or(0),
+            };
+            if expected > 0 {
+                let _ = tx.send(ExportMsg::Progress((frames_written as f32 / expected as f32).min(1.0)));
+            }
+        }
+
+        match writer {
+            Some(writer) => writer.finalize(),
+            None => Err("No audio could be decoded".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust_audio_player-{}-{}", std::process::id(), name))
+    }
+
+    fn run(source: &Path, dest: PathBuf, format: ExportFormat) -> Result<PathBuf, String> {
+        let mut job = ExportJob::start(&source.display().to_string(), dest, format, None);
+        while job.result().is_none() {
+            thread::sleep(Duration::from_millis(5));
+            job.poll();
+        }
+        job.result().unwrap().clone()
+    }
+
+    // Writes a stereo WAV source at the given sample size
+    fn write_source(path: &Path, bits_per_sample: u16, samples: &[i32]) {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    // Decodes an exported file back to samples at its stored size, along with that size
+    fn decode(path: &Path) -> (Vec<i32>, u32) {
+        let mut reader = get_probe()
+            .format(
+                Hint::new().with_extension("flac"),
+                MediaSourceStream::new(Box::new(File::open(path).unwrap()), Default::default()),
+                &Default::default(),
+                &Default::default(),
+            )
+            .unwrap()
+            .format;
+        let params = reader.default_track().unwrap().codec_params.clone();
+        let bits_per_sample = params.bits_per_sample.unwrap();
+        let mut decoder = get_codecs().make(&params, &Default::default()).unwrap();
+        let mut samples = Vec::new();
+        while let Ok(packet) = reader.next_packet() {
+            let decoded = decoder.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<i32>::new(decoded.capacity() as u64, *decoded.spec());
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend(buffer.samples().iter().map(|&sample| sample >> (32 - bits_per_sample)));
+        }
+        (samples, bits_per_sample)
+    }
+
+    #[test]
+    fn flac_export_keeps_every_sample() {
+        let source = temp_file("export-source.wav");
+        let samples: Vec<i32> = (0..20_000).map(|i| (i * 37) % 2000 - 1000).collect();
+        write_source(&source, 16, &samples);
+
+        let dest = run(&source, temp_file("export.flac"), ExportFormat::Flac).unwrap();
+        let (exported, bits_per_sample) = decode(&dest);
+
+        fs::remove_file(&source).unwrap();
+        fs::remove_file(&dest).unwrap();
+        assert_eq!(bits_per_sample, 16);
+        assert!(exported == samples);
+    }
+
+    #[test]
+    fn flac_export_keeps_24_bit_depth() {
+        let source = temp_file("export-source-24.wav");
+        // Steps of 3 are below 16-bit resolution, so truncation would flatten them
+        let samples: Vec<i32> = (0..20_000).map(|i| (i * 3) % 8_000_000 - 4_000_000).collect();
+        write_source(&source, 24, &samples);
+
+        let dest = run(&source, temp_file("export-24.flac"), ExportFormat::Flac).unwrap();
+        let (exported, bits_per_sample) = decode(&dest);
+
+        fs::remove_file(&source).unwrap();
+        fs::remove_file(&dest).unwrap();
+        assert_eq!(bits_per_sample, 24);
+        assert!(exported == samples);
+    }
+
+    #[test]
+    fn formats_without_an_encoder_fail_with_the_reason() {
+        let source = temp_file("export-missing-encoder.wav");
+        let err = run(&source, temp_file("export.mp3"), ExportFormat::Mp3).unwrap_err();
+        assert_eq!(Some(err.as_str()), ExportFormat::Mp3.unavailable_reason());
+    }
+}
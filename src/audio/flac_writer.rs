@@ -0,0 +1,491 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Frames per FLAC block, the reference encoder's default
+const BLOCK_SIZE: usize = 4096;
+
+// Highest fixed predictor order the format defines
+const MAX_FIXED_ORDER: usize = 4;
+
+// Highest residual partition order tried; more rarely pays off at this block size
+const MAX_PARTITION_ORDER: u32 = 6;
+
+// Sample sizes above this code their Rice parameters in 5 bits rather than 4, since residuals of
+// wider samples need parameters past 4-bit's 14
+const MAX_NARROW_RICE_BITS: u32 = 16;
+
+// Byte offset of the STREAMINFO body, after the "fLaC" marker and the block header
+const STREAMINFO_OFFSET: u64 = 8;
+
+// Writes 4- to 24-bit PCM as a FLAC file with fixed-predictor subframes, the same lossless
+// compression as `flac -1` without the stereo decorrelation
+pub struct FlacWriter {
+    writer: BufWriter<File>,
+    channels: usize,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    // Interleaved samples waiting to fill a block
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+    min_frame_bytes: usize,
+    max_frame_bytes: usize,
+}
+
+impl FlacWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32, bits_per_sample: u32) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "FLAC supports 1 to 8 channels"));
+        }
+        if !(1..1 << 20).contains(&sample_rate) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sample rate is out of range for FLAC"));
+        }
+        if !(4..=24).contains(&bits_per_sample) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "FLAC export supports 4 to 24 bits per sample"));
+        }
+
+        let mut writer = Self {
+            writer: BufWriter::new(File::create(path)?),
+            channels: channels as usize,
+            sample_rate,
+            bits_per_sample,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_frames: 0,
+            min_frame_bytes: 0,
+            max_frame_bytes: 0,
+        };
+        writer.writer.write_all(b"fLaC")?;
+        // Last metadata block, type 0 (STREAMINFO), 34 bytes long
+        writer.writer.write_all(&[0x80, 0, 0, 34])?;
+        writer.write_stream_info()?;
+        Ok(writer)
+    }
+
+    // Adds interleaved samples, writing out every block that fills up. Samples must fit the
+    // writer's sample size.
+    pub fn write_samples(&mut self, samples: &[i32]) -> io::Result<()> {
+        let block_samples = BLOCK_SIZE * self.channels;
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let take = (block_samples - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() == block_samples {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the last partial block and fills in the stream totals
+    pub fn finalize(mut self) -> io::Result<()> {
+        // A trailing partial frame can't be encoded, so it's dropped
+        let partial = self.pending.len() % self.channels;
+        self.pending.truncate(self.pending.len() - partial);
+        if !self.pending.is_empty() {
+            self.write_block()?;
+        }
+        self.writer.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+        self.write_stream_info()?;
+        self.writer.flush()
+    }
+
+    fn write_stream_info(&mut self) -> io::Result<()> {
+        let stream_info = stream_info(
+            self.channels,
+            self.sample_rate,
+            self.bits_per_sample,
+            self.total_frames,
+            (self.min_frame_bytes, self.max_frame_bytes),
+        );
+        self.writer.write_all(&stream_info)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let frames = self.pending.len() / self.channels;
+        let frame = encode_frame(&self.pending, self.channels, self.bits_per_sample, self.frame_number);
+        self.writer.write_all(&frame)?;
+        let frame_bytes = frame.len();
+        self.min_frame_bytes = if self.frame_number == 0 { frame_bytes } else { self.min_frame_bytes.min(frame_bytes) };
+        self.max_frame_bytes = self.max_frame_bytes.max(frame_bytes);
+        self.frame_number += 1;
+        self.total_frames += frames as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// The 34-byte STREAMINFO block body. Frame sizes are (min, max) in bytes, 0 when unknown.
+pub(crate) fn stream_info(
+    channels: usize,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    total_frames: u64,
+    frame_bytes: (usize, usize),
+) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.write(BLOCK_SIZE as u64, 16);
+    bits.write(BLOCK_SIZE as u64, 16);
+    bits.write(frame_bytes.0 as u64, 24);
+    bits.write(frame_bytes.1 as u64, 24);
+    bits.write(sample_rate as u64, 20);
+    bits.write(channels as u64 - 1, 3);
+    bits.write(bits_per_sample as u64 - 1, 5);
+    bits.write(total_frames, 36);
+    // An all-zero MD5 signature means none was computed
+    bits.bytes.extend_from_slice(&[0; 16]);
+    bits.bytes
+}
+
+// Encodes up to a block of interleaved samples as one FLAC frame
+pub(crate) fn encode_frame(samples: &[i32], channels: usize, bits_per_sample: u32, frame_number: u64) -> Vec<u8> {
+    let frames = samples.len() / channels;
+    let mut bits = BitWriter::default();
+
+    // Sync code, fixed-blocksize stream, block size and sample rate/size taken from the
+    // header's tail and STREAMINFO, independent channels
+    bits.write(0x3FFE, 14);
+    bits.write(0, 2);
+    bits.write(0b0111, 4);
+    bits.write(0, 4);
+    bits.write(channels as u64 - 1, 4);
+    bits.write(0, 4);
+    bits.write_utf8(frame_number);
+    bits.write(frames as u64 - 1, 16);
+    let crc = crc8(&bits.bytes);
+    bits.write(crc as u64, 8);
+
+    let mut channel = Vec::with_capacity(frames);
+    for index in 0..channels {
+        channel.clear();
+        channel.extend(samples.iter().skip(index).step_by(channels));
+        write_subframe(&mut bits, &channel, bits_per_sample);
+    }
+    bits.align();
+    let crc = crc16(&bits.bytes);
+    bits.write(crc as u64, 16);
+    bits.bytes
+}
+
+// Best residual coding found for one predictor order
+struct Residual {
+    order: usize,
+    partition_order: u32,
+    parameters: Vec<u32>,
+    bits: u64,
+}
+
+// Encodes one channel of a block as whichever of a constant, fixed predictor or verbatim
+// subframe comes out smallest
+fn write_subframe(bits: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    let parameter_bits = if bits_per_sample > MAX_NARROW_RICE_BITS { 5 } else { 4 };
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| plan_residual(order, &fixed_residual(samples, order), parameter_bits))
+        .min_by_key(|residual| residual.bits + (residual.order as u64 * bits_per_sample as u64))
+        .expect("order 0 always fits");
+
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    if best.bits + best.order as u64 * bits_per_sample as u64 >= verbatim_bits {
+        bits.write(0b0000_0010, 8);
+        for &sample in samples {
+            bits.write_signed(sample, bits_per_sample);
+        }
+        return;
+    }
+
+    bits.write(0b0001_0000 | (best.order as u64) << 1, 8);
+    for &sample in &samples[..best.order] {
+        bits.write_signed(sample, bits_per_sample);
+    }
+
+    let residual = fixed_residual(samples, best.order);
+    // Rice coding with 4-bit (method 0) or 5-bit (method 1) parameters
+    bits.write(parameter_bits as u64 - 4, 2);
+    bits.write(best.partition_order as u64, 4);
+    let partition_len = samples.len() >> best.partition_order;
+    let mut start = 0;
+    for (partition, &parameter) in best.parameters.iter().enumerate() {
+        let end = (partition + 1) * partition_len - best.order;
+        bits.write(parameter as u64, parameter_bits);
+        for &value in &residual[start..end] {
+            bits.write_rice(zigzag(value), parameter);
+        }
+        start = end;
+    }
+}
+
+// Prediction error of the fixed polynomial predictor of the given order, one value per sample
+// after the warm-up samples
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i32> {
+    let mut residual = samples.to_vec();
+    for pass in 0..order {
+        for i in (pass + 1..residual.len()).rev() {
+            residual[i] -= residual[i - 1];
+        }
+    }
+    residual.split_off(order)
+}
+
+// Picks the partition order and per-partition Rice parameters that code a residual in about the
+// fewest bits. Partitions must split the block evenly, and the first one loses the warm-up samples.
+fn plan_residual(order: usize, residual: &[i32], parameter_bits: u32) -> Residual {
+    let block_len = residual.len() + order;
+    let finest = (0..=MAX_PARTITION_ORDER)
+        .take_while(|&partition_order| {
+            let partitions = 1 << partition_order;
+            block_len.is_multiple_of(partitions) && block_len / partitions > order
+        })
+        .last()
+        .unwrap_or(0);
+
+    // Sums and lengths of the finest partitions; coarser ones merge neighbouring pairs
+    let partition_len = block_len >> finest;
+    let mut partitions: Vec<(u64, usize)> = (0..1usize << finest)
+        .map(|partition| {
+            let start = (partition * partition_len).saturating_sub(order);
+            let end = (partition + 1) * partition_len - order;
+            let sum = residual[start..end].iter().map(|&value| zigzag(value) as u64).sum();
+            (sum, end - start)
+        })
+        .collect();
+
+    let mut best: Option<Residual> = None;
+    for partition_order in (0..=finest).rev() {
+        let (parameters, bits): (Vec<u32>, Vec<u64>) =
+            partitions.iter().map(|&(sum, len)| rice_parameter(sum, len, parameter_bits)).unzip();
+        let total = 2 + 4 + parameter_bits as u64 * parameters.len() as u64 + bits.iter().sum::<u64>();
+        if best.as_ref().is_none_or(|best| total < best.bits) {
+            best = Some(Residual {
+                order,
+                partition_order,
+                parameters,
+                bits: total,
+            });
+        }
+        partitions = partitions.chunks(2).map(|pair| pair.iter().fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1))).collect();
+    }
+    best.expect("partition order 0 always fits")
+}
+
+// Rice parameter for a run of values with the given sum, and roughly the bits it codes them in.
+// The all-ones parameter is the escape code, so the largest usable one is one below it.
+fn rice_parameter(sum: u64, len: usize, parameter_bits: u32) -> (u32, u64) {
+    let max_parameter = (1 << parameter_bits) - 2;
+    let len = len as u64;
+    let mean = sum / len.max(1);
+    let estimate = if mean == 0 { 0 } else { (63 - mean.leading_zeros()).min(max_parameter) };
+
+    // The estimate from the mean is close; one step either side is sometimes better
+    (estimate.saturating_sub(1)..=(estimate + 1).min(max_parameter))
+        .map(|parameter| (parameter, len * (parameter as u64 + 1) + (sum >> parameter)))
+        .min_by_key(|&(_, bits)| bits)
+        .expect("the range is never empty")
+}
+
+// Folds signed values onto unsigned ones, small magnitudes first
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    // Bits not yet filling a whole byte, in the low end
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    // Appends the low `bits` bits of a value, at most 56 at a time
+    fn write(&mut self, value: u64, bits: u32) {
+        self.pending = self.pending << bits | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+        self.pending &= (1 << self.pending_bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    fn write_rice(&mut self, value: u32, parameter: u32) {
+        // The quotient in unary: that many zeros, then a one
+        let mut quotient = value >> parameter;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        self.write(1, quotient + 1);
+        self.write(value as u64, parameter);
+    }
+
+    // Frame numbers use the UTF-8 scheme extended to 36 bits
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        let continuation_bytes = match value {
+            0x80..0x800 => 1,
+            0x800..0x1_0000 => 2,
+            0x1_0000..0x20_0000 => 3,
+            0x20_0000..0x400_0000 => 4,
+            0x400_0000..0x8000_0000 => 5,
+            _ => 6,
+        };
+        let lead_marker = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+        self.write(lead_marker | value >> (6 * continuation_bytes), 8);
+        for byte in (0..continuation_bytes).rev() {
+            self.write(0x80 | (value >> (6 * byte) & 0x3F), 8);
+        }
+    }
+
+    // Pads with zero bits to the next byte boundary
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { crc << 1 ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::errors::Error;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::probe::Hint;
+    use symphonia::default::{get_codecs, get_probe};
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust_audio_player-{}-{}", std::process::id(), name))
+    }
+
+    // Decodes a FLAC file back to interleaved samples at their stored size, with its channel
+    // count and sample rate
+    fn decode(path: &Path) -> (Vec<i32>, usize, u32) {
+        let mss = MediaSourceStream::new(Box::new(File::open(path).unwrap()), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let mut reader = get_probe()
+            .format(&hint, mss, &Default::default(), &Default::default())
+            .unwrap()
+            .format;
+        let params = reader.default_track().unwrap().codec_params.clone();
+        let shift = 32 - params.bits_per_sample.unwrap();
+        let mut decoder = get_codecs().make(&params, &Default::default()).unwrap();
+
+        let mut samples = Vec::new();
+        loop {
+            let packet = match reader.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(_)) => break,
+                Err(err) => panic!("{}", err),
+            };
+            let decoded = decoder.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<i32>::new(decoded.capacity() as u64, *decoded.spec());
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend(buffer.samples().iter().map(|&sample| sample >> shift));
+        }
+        (samples, params.channels.unwrap().count(), params.sample_rate.unwrap())
+    }
+
+    // Writes and decodes samples, returning the size of the file
+    fn round_trip(name: &str, channels: u16, bits_per_sample: u32, samples: &[i32]) -> u64 {
+        let path = temp_file(name);
+        let mut writer = FlacWriter::create(&path, channels, 22_050, bits_per_sample).unwrap();
+        // Uneven writes so blocks fill across calls
+        for chunk in samples.chunks(1000 * channels as usize + 3) {
+            writer.write_samples(chunk).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (decoded, decoded_channels, rate) = decode(&path);
+        let size = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((decoded_channels, rate), (channels as usize, 22_050));
+        assert_eq!(decoded.len(), samples.len());
+        assert!(decoded == samples, "{} decodes to different samples", name);
+        size
+    }
+
+    #[test]
+    fn stereo_music_decodes_losslessly() {
+        let samples: Vec<i32> = (0..3 * BLOCK_SIZE + 1234)
+            .flat_map(|i| {
+                let t = i as f32 / 22_050.0;
+                let left = (t * 440.0 * std::f32::consts::TAU).sin() * 20_000.0;
+                let right = (t * 97.0 * std::f32::consts::TAU).sin() * 30_000.0 + (i % 7) as f32 * 100.0;
+                [left as i32, right as i32]
+            })
+            .collect();
+        let size = round_trip("music.flac", 2, 16, &samples);
+        assert!(size < samples.len() as u64 * 2 * 3 / 4, "{} bytes is barely compressed", size);
+    }
+
+    #[test]
+    fn extreme_and_silent_blocks_decode_losslessly() {
+        // Full-scale noise leaves nothing to predict, then a block of silence and a short tail
+        let mut samples: Vec<i32> = (0..BLOCK_SIZE).map(|_| fastrand::i16(..) as i32).collect();
+        samples.extend(std::iter::repeat_n(0, BLOCK_SIZE));
+        samples.extend([i16::MIN as i32, i16::MAX as i32, i16::MIN as i32, 5, -5]);
+        round_trip("extremes.flac", 1, 16, &samples);
+    }
+
+    #[test]
+    fn samples_wider_than_16_bits_decode_losslessly() {
+        const MAX_24: i32 = (1 << 23) - 1;
+        // A quiet tone whose low bits would be lost at 16 bits, full-scale noise and the extremes
+        let mut samples: Vec<i32> = (0..BLOCK_SIZE)
+            .map(|i| ((i as f32 / 40.0).sin() * 3000.0) as i32)
+            .chain((0..BLOCK_SIZE).map(|_| fastrand::i32(-MAX_24 - 1..=MAX_24)))
+            .collect();
+        samples.extend([-MAX_24 - 1, MAX_24, -MAX_24 - 1, 1, -1]);
+        round_trip("24-bit.flac", 1, 24, &samples);
+    }
+
+    #[test]
+    fn frame_numbers_use_the_extended_utf8_scheme() {
+        let encode = |value| {
+            let mut bits = BitWriter::default();
+            bits.write_utf8(value);
+            bits.bytes
+        };
+        assert_eq!(encode(0x7F), [0x7F]);
+        assert_eq!(encode(0x80), [0xC2, 0x80]);
+        assert_eq!(encode(0x1_0000), [0xF0, 0x90, 0x80, 0x80]);
+    }
+}
@@ -1,2 +1,8 @@
+pub(crate) mod crossfade;
+pub(crate) mod effects;
+pub(crate) mod export;
+pub(crate) mod flac_writer;
 pub(crate) mod player;
-pub(crate) mod waveform;
\ No newline at end of file
+pub(crate) mod spectrum;
+pub(crate) mod waveform;
+pub(crate) mod waveform_cache;
\ No newline at end of file
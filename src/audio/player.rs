@@ -1,16 +1,104 @@
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use crate::audio::crossfade::{spawn_crossfade, spawn_fade_out, CrossfadeCurve};
+use crate::audio::effects::{
+    Compressor, CompressorControl, GainControl, MonoSum, MonoSumControl, PitchControl, PitchShift, PositionControl,
+    PositionTracker, SmoothGain,
+};
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+use rodio::cpal::traits::HostTrait;
+use rodio::{Decoder, Device, DeviceTrait, OutputStream, OutputStreamHandle, PlayError, Sink, Source, StreamError};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use thiserror::Error;
+
+// Why playback or seeking failed, worded so it can be shown to the user as is
+#[derive(Debug, Error)]
+pub enum PlayerError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not decode the file: {0}")]
+    Decode(String),
+    #[error("No audio output device found ({0})")]
+    NoOutputDevice(String),
+    #[error("{0} is not supported")]
+    Unsupported(String),
+}
+
+impl From<DecoderError> for PlayerError {
+    fn from(err: DecoderError) -> Self {
+        match err {
+            DecoderError::UnrecognizedFormat => Self::Unsupported("This audio format".to_string()),
+            DecoderError::IoError(message) => Self::Io(io::Error::other(message)),
+            err => Self::Decode(err.to_string()),
+        }
+    }
+}
+
+impl From<SeekError> for PlayerError {
+    fn from(err: SeekError) -> Self {
+        match err {
+            SeekError::NotSupported { .. } => Self::Unsupported("Seeking in this file".to_string()),
+            err => Self::Decode(err.to_string()),
+        }
+    }
+}
+
+impl From<StreamError> for PlayerError {
+    fn from(err: StreamError) -> Self {
+        Self::NoOutputDevice(err.to_string())
+    }
+}
+
+impl From<PlayError> for PlayerError {
+    fn from(err: PlayError) -> Self {
+        match err {
+            PlayError::DecoderError(err) => err.into(),
+            PlayError::NoDevice => Self::NoOutputDevice("the device was lost".to_string()),
+        }
+    }
+}
+
+// Upper bound for the combined playback gain so stacked gain offsets can't blast the output
+const MAX_SINK_VOLUME: f32 = 2.0;
 
 pub struct AudioPlayer {
     _stream: Option<OutputStream>,
     stream_handle: Option<OutputStreamHandle>,
     sink: Option<Arc<Mutex<Sink>>>,
-    start_time: Option<Instant>,
-    pause_duration: Duration,
     playing_file: Option<String>,
+    compressor: CompressorControl,
+    mono_sum: MonoSumControl,
+    pitch: PitchControl,
+    // Playback rate kept so each new sink starts at the same speed
+    speed: f32,
+    // Master volume as a linear factor, kept so each new sink starts at the same level
+    volume: f32,
+    // Volume to restore when unmuting; Some while muted
+    pre_mute_volume: Option<f32>,
+    // Per-track gain offset in dB
+    track_gain_db: f32,
+    // Temporary offset in dB used to level-match tracks while comparing them
+    match_gain_db: f32,
+    // Loudness normalization offset in dB from the track's ReplayGain tag
+    replay_gain_db: f32,
+    // Gain of the current sink's source. Each sink gets its own so a fading-out track keeps its level.
+    gain: GainControl,
+    // Position of the current track in track time
+    position: PositionControl,
+    // Track appended behind the current one on the same sink for a gapless transition, with its
+    // gain control, position and track gain
+    next: Option<(String, GainControl, PositionControl, f32)>,
+    // Overlap between consecutive tracks; zero switches immediately
+    crossfade: Duration,
+    crossfade_curve: CrossfadeCurve,
+    // Fade applied when a track starts and when it is stopped; zero cuts immediately
+    fade: Duration,
+    // Output device chosen by name; None uses the system default
+    output_device: Option<String>,
+    // Name of the chosen device when it couldn't be found and the default was opened instead
+    device_fallback: Option<String>,
 }
 
 impl Default for AudioPlayer {
@@ -19,46 +107,118 @@ impl Default for AudioPlayer {
             _stream: None,
             stream_handle: None,
             sink: None,
-            start_time: None,
-            pause_duration: Duration::ZERO,
             playing_file: None,
+            compressor: CompressorControl::default(),
+            mono_sum: MonoSumControl::default(),
+            pitch: PitchControl::default(),
+            speed: 1.0,
+            volume: 1.0,
+            pre_mute_volume: None,
+            track_gain_db: 0.0,
+            match_gain_db: 0.0,
+            replay_gain_db: 0.0,
+            gain: GainControl::default(),
+            position: PositionControl::default(),
+            next: None,
+            crossfade: Duration::ZERO,
+            crossfade_curve: CrossfadeCurve::default(),
+            fade: Duration::ZERO,
+            output_device: None,
+            device_fallback: None,
         }
     }
 }
 
 impl AudioPlayer {
-    pub fn play(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn play(&mut self, file_path: &str) -> Result<(), PlayerError> {
+        let outgoing = self.take_crossfade_source();
         self.stop();
 
-        let (stream, stream_handle) = OutputStream::try_default()?;
-        let sink = Sink::try_new(&stream_handle)?;
+        let sink = Sink::try_new(self.ensure_output()?)?;
+        sink.set_speed(self.speed);
 
-        let file = File::open(file_path)?;
-        let source = Decoder::new(BufReader::new(file))?;
+        self.gain = GainControl::new(self.sink_volume());
+        self.position = PositionControl::default();
+        let source = self.open_source(file_path, self.gain.clone(), self.position.clone())?;
+
+        // A crossfade already ramps the incoming track in
+        if self.fade.is_zero() || outgoing.is_some() {
+            sink.append(source);
+        } else {
+            sink.append(source.fade_in(self.fade));
+        }
 
-        sink.append(source);
+        let sink = Arc::new(Mutex::new(sink));
+        if let Some(outgoing) = outgoing {
+            spawn_crossfade(outgoing, sink.clone(), self.crossfade, self.crossfade_curve, 1.0);
+        }
 
-        self._stream = Some(stream);
-        self.stream_handle = Some(stream_handle);
-        self.sink = Some(Arc::new(Mutex::new(sink)));
-        self.start_time = Some(Instant::now());
-        self.pause_duration = Duration::ZERO;
+        self.sink = Some(sink);
         self.playing_file = Some(file_path.to_string());
 
         Ok(())
     }
 
+    // Decodes a file through the effect chain, ending in the given gain control and position
+    fn open_source(
+        &self,
+        file_path: &str,
+        gain: GainControl,
+        position: PositionControl,
+    ) -> Result<impl Source<Item = f32> + use<>, PlayerError> {
+        let file = File::open(file_path)?;
+        let source = Decoder::new(BufReader::new(file))?.convert_samples::<f32>();
+        let source = MonoSum::new(source, self.mono_sum.clone());
+        let source = PitchShift::new(source, self.pitch.clone());
+        let source = Compressor::new(source, self.compressor.clone());
+        Ok(PositionTracker::new(SmoothGain::new(source, gain), position))
+    }
+
+    // Appends a track to the current sink so it starts the moment the current one ends, with no
+    // gap for reopening. Only one track is held back at a time.
+    pub fn enqueue(&mut self, file_path: &str, track_gain_db: f32) -> Result<(), PlayerError> {
+        let Some(sink) = self.sink.clone() else {
+            return Ok(());
+        };
+
+        let gain = GainControl::new(self.volume_with_gain(track_gain_db));
+        let position = PositionControl::default();
+        let source = self.open_source(file_path, gain.clone(), position.clone())?;
+        sink.lock().unwrap().append(source);
+        self.next = Some((file_path.to_string(), gain, position, track_gain_db));
+        Ok(())
+    }
+
+    pub fn has_enqueued(&self) -> bool {
+        self.next.is_some()
+    }
+
+    // Makes the enqueued track current once the sink has moved on to it, returning its path
+    pub fn advance_enqueued(&mut self) -> Option<String> {
+        let sink = self.sink.as_ref()?;
+        if self.next.is_none() || sink.lock().unwrap().len() > 1 {
+            return None;
+        }
+
+        let (file, gain, position, track_gain_db) = self.next.take()?;
+        self.playing_file = Some(file.clone());
+        self.gain = gain;
+        self.position = position;
+        self.track_gain_db = track_gain_db;
+        self.match_gain_db = 0.0;
+        // The enqueued gain didn't include the normalization offset, which still holds when a
+        // track follows itself; a different track gets its own offset once its tags are read
+        self.gain.set_target(self.sink_volume());
+        Some(file)
+    }
+
     pub fn pause(&mut self) {
         if let Some(sink) = &self.sink {
-            let sink_guard = sink.lock().unwrap();
-            sink_guard.pause();
-            if let Some(start) = self.start_time.take() {
-                self.pause_duration += Instant::now() - start;
-            }
+            sink.lock().unwrap().pause();
         }
     }
 
-    pub fn resume(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn resume(&mut self) -> Result<(), PlayerError> {
         let should_resume = if let Some(sink_arc) = &self.sink {
             sink_arc.lock().unwrap().is_paused()
         } else {
@@ -75,7 +235,6 @@ impl AudioPlayer {
             if let Some(sink_arc) = &self.sink {
                 sink_arc.lock().unwrap().play();
             }
-            self.start_time = Some(Instant::now());
             Ok(())
         } else if let Some(file_path) = file_path_to_play {
             self.play(&file_path)
@@ -84,15 +243,159 @@ impl AudioPlayer {
         }
     }
 
+    pub fn set_crossfade(&mut self, duration: Duration, curve: CrossfadeCurve) {
+        self.crossfade = duration;
+        self.crossfade_curve = curve;
+    }
+
+    pub fn set_fade(&mut self, duration: Duration) {
+        self.fade = duration;
+    }
+
+    // Detaches the current sink so it can fade out under the next track, if crossfading applies
+    fn take_crossfade_source(&mut self) -> Option<Arc<Mutex<Sink>>> {
+        if self.crossfade.is_zero() {
+            return None;
+        }
+
+        let audible = self.sink.as_ref().is_some_and(|sink| {
+            let sink = sink.lock().unwrap();
+            !sink.is_paused() && !sink.empty()
+        });
+        if audible { self.sink.take() } else { None }
+    }
+
+    // Names of the output devices currently available
+    pub fn output_devices() -> Vec<String> {
+        rodio::cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    fn find_output_device(name: &str) -> Option<Device> {
+        rodio::cpal::default_host()
+            .output_devices()
+            .ok()?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+    }
+
+    // Routes output to the named device, or the system default for None. A track that is
+    // playing or paused moves over at its current position.
+    pub fn set_output_device(&mut self, name: Option<String>) -> Result<(), PlayerError> {
+        if name == self.output_device {
+            return Ok(());
+        }
+        self.output_device = name;
+        if self.stream_handle.is_none() {
+            return Ok(());
+        }
+
+        let current = match &self.playing_file {
+            Some(file) if self.sink.is_some() && !self.is_finished() => {
+                Some((file.clone(), self.progress(), self.is_paused()))
+            }
+            _ => None,
+        };
+        self.release_output();
+
+        if let Some((file, position, paused)) = current {
+            self.play(&file)?;
+            self.seek(position)?;
+            if paused {
+                self.pause();
+            }
+        }
+        Ok(())
+    }
+
+    // Takes the name of a chosen device that had disappeared when the output was last opened
+    pub fn take_device_fallback(&mut self) -> Option<String> {
+        self.device_fallback.take()
+    }
+
+    // Opens the output device if it was never opened or has been released. A chosen device that
+    // is no longer present falls back to the default.
+    fn ensure_output(&mut self) -> Result<&OutputStreamHandle, PlayerError> {
+        if self.stream_handle.is_none() {
+            let device = self.output_device.as_deref().map(|name| (name, Self::find_output_device(name)));
+            let (stream, stream_handle) = match device {
+                Some((_, Some(device))) => OutputStream::try_from_device(&device)?,
+                Some((name, None)) => {
+                    self.device_fallback = Some(name.to_string());
+                    OutputStream::try_default()?
+                }
+                None => OutputStream::try_default()?,
+            };
+            self._stream = Some(stream);
+            self.stream_handle = Some(stream_handle);
+        }
+        Ok(self.stream_handle.as_ref().unwrap())
+    }
+
+    // Stops playback, fading out first when a fade is set and the track is audible
     pub fn stop(&mut self) {
-        if let Some(sink) = &self.sink {
+        self.next = None;
+        let Some(sink) = self.sink.take() else {
+            return;
+        };
+
+        let audible = {
+            let sink = sink.lock().unwrap();
+            !sink.is_paused() && !sink.empty()
+        };
+        if audible && !self.fade.is_zero() {
+            spawn_fade_out(sink, self.fade);
+        } else {
             sink.lock().unwrap().stop();
         }
+    }
+
+    fn stop_immediately(&mut self) {
+        self.next = None;
+        if let Some(sink) = self.sink.take() {
+            sink.lock().unwrap().stop();
+        }
+    }
+
+    // Stops playback and closes the output device so other applications can use it
+    pub fn release_output(&mut self) {
+        self.stop_immediately();
         self._stream = None;
         self.stream_handle = None;
-        self.sink = None;
-        self.start_time = None;
-        self.pause_duration = Duration::ZERO;
+    }
+
+    // True when the output device is open but nothing is playing or paused on it
+    pub fn is_idle(&self) -> bool {
+        self.stream_handle.is_some() && (self.sink.is_none() || self.is_finished())
+    }
+
+    // True while audio is actively being output
+    pub fn is_playing(&self) -> bool {
+        if let Some(sink) = &self.sink {
+            let sink = sink.lock().unwrap();
+            !sink.is_paused() && !sink.empty()
+        } else {
+            false
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().empty()
+        } else {
+            false
+        }
+    }
+
+    // Seeks to a position in track time. The sink scales seeks by its speed as if they were in
+    // playback time, so the position is scaled back first. Seeking is approximate for some
+    // compressed formats.
+    pub fn seek(&mut self, position: Duration) -> Result<(), PlayerError> {
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().try_seek(position.div_f32(self.speed))?;
+        }
+        Ok(())
     }
 
     pub fn is_paused(&self) -> bool {
@@ -105,16 +408,11 @@ impl AudioPlayer {
     }
 
 
+    // Position within the current track. This is counted in track time rather than taken from
+    // the sink, so it stays aligned with the waveform even if the playback speed changes mid-track.
     pub fn progress(&self) -> Duration {
-        if let Some(sink) = &self.sink {
-            let sink_guard = sink.lock().unwrap();
-            if sink_guard.is_paused() {
-                self.pause_duration
-            } else if let Some(start) = self.start_time {
-                self.pause_duration + (Instant::now() - start)
-            } else {
-                Duration::ZERO
-            }
+        if self.sink.is_some() {
+            self.position.position()
         } else {
             Duration::ZERO
         }
@@ -123,4 +421,185 @@ impl AudioPlayer {
     pub fn current_file(&self) -> Option<&str> {
         self.playing_file.as_deref()
     }
-}
\ No newline at end of file
+
+    // Sets the playback rate. This resamples, so pitch rises and falls with the speed; the pitch
+    // control can transpose it back. Progress stays in track time either way.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(0.25, 4.0);
+        if let Some(sink) = &self.sink {
+            sink.lock().unwrap().set_speed(self.speed);
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    // Sets the master volume, ramped on the audio thread like the other gain changes
+    pub fn set_volume(&mut self, volume: f32) {
+        // Moving the volume explicitly takes over from any mute
+        self.pre_mute_volume = None;
+        self.volume = volume.max(0.0);
+        self.gain.set_target(self.sink_volume());
+    }
+
+    pub fn toggle_mute(&mut self) {
+        match self.pre_mute_volume.take() {
+            Some(volume) => self.volume = volume,
+            None => {
+                self.pre_mute_volume = Some(self.volume);
+                self.volume = 0.0;
+            }
+        }
+        self.gain.set_target(self.sink_volume());
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.pre_mute_volume.is_some()
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    // Sets the gain offset for the current track. The change is ramped on the audio thread so
+    // dragging the control doesn't produce audible stepping.
+    pub fn set_track_gain_db(&mut self, gain_db: f32) {
+        self.track_gain_db = gain_db;
+        self.gain.set_target(self.sink_volume());
+    }
+
+    pub fn track_gain_db(&self) -> f32 {
+        self.track_gain_db
+    }
+
+    // Sets the comparison level-matching offset, applied on top of the track gain
+    pub fn set_match_gain_db(&mut self, gain_db: f32) {
+        self.match_gain_db = gain_db;
+        self.gain.set_target(self.sink_volume());
+    }
+
+    // Sets the loudness normalization offset, applied on top of the track gain
+    pub fn set_replay_gain_db(&mut self, gain_db: f32) {
+        self.replay_gain_db = gain_db;
+        self.gain.set_target(self.sink_volume());
+    }
+
+    // Linear gain with all offsets combined, clamped to a safe range
+    fn sink_volume(&self) -> f32 {
+        self.volume_with_gain(self.track_gain_db + self.match_gain_db + self.replay_gain_db)
+    }
+
+    fn volume_with_gain(&self, gain_db: f32) -> f32 {
+        (self.volume * 10f32.powf(gain_db / 20.0)).clamp(0.0, MAX_SINK_VOLUME)
+    }
+
+    // Shared control for the night-mode compressor; changes apply to the current and future sinks
+    pub fn compressor(&self) -> &CompressorControl {
+        &self.compressor
+    }
+
+    // Transposes playback by whole semitones (±12) without changing tempo; 0 disables it
+    pub fn set_pitch_semitones(&mut self, semitones: i32) {
+        self.pitch.set_semitones(semitones);
+    }
+
+    pub fn pitch_semitones(&self) -> i32 {
+        self.pitch.semitones()
+    }
+
+    // Shared control for summing the output to mono, used by the momentary mono check
+    pub fn mono_sum(&self) -> &MonoSumControl {
+        &self.mono_sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use rodio::queue::SourcesQueueOutput;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    const RATE: u32 = 1000;
+    // Allowed error; the sink applies control changes every 5 ms of audio
+    const TOLERANCE: f64 = 0.02;
+
+    // A player on a sink with no device behind it, playing ten seconds of mono audio. Audio plays
+    // only as samples are pulled from the returned output.
+    fn idle_player() -> (AudioPlayer, SourcesQueueOutput<f32>) {
+        let (sink, output) = Sink::new_idle();
+        let mut player = AudioPlayer::default();
+        let source = SamplesBuffer::new(1, RATE, vec![0.1f32; 10 * RATE as usize]);
+        sink.append(PositionTracker::new(source, player.position.clone()));
+        player.sink = Some(Arc::new(Mutex::new(sink)));
+        (player, output)
+    }
+
+    fn play_for(output: &mut SourcesQueueOutput<f32>, track_secs: f64) {
+        output.by_ref().take((track_secs * RATE as f64) as usize).for_each(drop);
+    }
+
+    fn assert_position(player: &AudioPlayer, expected_secs: f64) {
+        let position = player.progress().as_secs_f64();
+        assert!((position - expected_secs).abs() < TOLERANCE, "at {} s, expected {} s", position, expected_secs);
+    }
+
+    #[test]
+    fn speed_change_keeps_the_position_in_track_time() {
+        let (mut player, mut output) = idle_player();
+        play_for(&mut output, 1.0);
+        assert_position(&player, 1.0);
+
+        player.set_speed(1.5);
+        play_for(&mut output, 1.5);
+        assert_position(&player, 2.5);
+
+        // Seeks land on track time too. The sink applies them while audio is pulled, so keep
+        // pulling until the seek returns.
+        let seeked = Arc::new(AtomicBool::new(false));
+        let pull = thread::spawn({
+            let seeked = seeked.clone();
+            move || {
+                while !seeked.load(Ordering::Relaxed) {
+                    play_for(&mut output, 0.001);
+                    thread::sleep(Duration::from_millis(1));
+                }
+                output
+            }
+        });
+        player.seek(Duration::from_secs(6)).unwrap();
+        seeked.store(true, Ordering::Relaxed);
+        let mut output = pull.join().unwrap();
+        play_for(&mut output, 1.0);
+        assert!(player.progress() >= Duration::from_secs(6));
+        assert!(player.progress() < Duration::from_secs_f64(7.2));
+    }
+
+    #[test]
+    fn progress_only_moves_forward_across_pause_and_resume() {
+        let (mut player, mut output) = idle_player();
+        let mut readings = Vec::new();
+        for _ in 0..5 {
+            play_for(&mut output, 0.1);
+            readings.push(player.progress());
+        }
+
+        player.pause();
+        for _ in 0..10 {
+            play_for(&mut output, 0.1);
+            readings.push(player.progress());
+        }
+        // Nothing of the track plays while paused
+        assert_position(&player, 0.5);
+
+        player.resume().unwrap();
+        for _ in 0..5 {
+            play_for(&mut output, 0.1);
+            readings.push(player.progress());
+        }
+        assert_position(&player, 1.0);
+        assert!(readings.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", readings);
+    }
+}
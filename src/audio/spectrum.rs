@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+
+// Samples per analysis frame and the step between frames, in waveform buffer samples
+pub const FFT_SIZE: usize = 1024;
+pub const HOP_SIZE: usize = 512;
+// Frequency rows per frame, from DC up to just below the Nyquist frequency
+pub const BINS: usize = FFT_SIZE / 2;
+
+// Magnitudes at or below this level map to zero
+const FLOOR_DB: f32 = -90.0;
+// Frames computed per update, so a whole track arriving at once is analysed over several frames
+const MAX_FRAMES_PER_UPDATE: usize = 400;
+
+// Windowed magnitude spectrum of fixed-size blocks
+struct Fft {
+    size: usize,
+    // Hann window applied before each transform, and the gain it removes
+    window: Vec<f32>,
+    window_gain: f32,
+    // exp(-2πik/N) for k < N/2
+    twiddles: Vec<(f32, f32)>,
+}
+
+impl Fft {
+    // `size` must be a power of two
+    fn new(size: usize) -> Self {
+        let window: Vec<f32> = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        let window_gain = window.iter().sum::<f32>() / 2.0;
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let (sin, cos) = (-2.0 * PI * k as f32 / size as f32).sin_cos();
+                (cos, sin)
+            })
+            .collect();
+
+        Self {
+            size,
+            window,
+            window_gain,
+            twiddles,
+        }
+    }
+
+    // Level of each bin below the Nyquist frequency in dBFS. `block` must hold `size` samples.
+    fn magnitudes_db(&self, block: &[f32]) -> Vec<f32> {
+        let mut re: Vec<f32> = block.iter().zip(&self.window).map(|(sample, weight)| sample * weight).collect();
+        let mut im = vec![0.0; self.size];
+        self.transform(&mut re, &mut im);
+
+        re.iter()
+            .zip(&im)
+            .take(self.size / 2)
+            .map(|(re, im)| 20.0 * ((re * re + im * im).sqrt() / self.window_gain).max(1e-9).log10())
+            .collect()
+    }
+
+    // In-place iterative radix-2 FFT
+    fn transform(&self, re: &mut [f32], im: &mut [f32]) {
+        let n = re.len();
+
+        let mut j = 0;
+        for i in 1..n {
+            let mut bit = n >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (cos, sin) = self.twiddles[k * stride];
+                    let a = start + k;
+                    let b = a + len / 2;
+                    let t_re = re[b] * cos - im[b] * sin;
+                    let t_im = re[b] * sin + im[b] * cos;
+                    re[b] = re[a] - t_re;
+                    im[b] = im[a] - t_im;
+                    re[a] += t_re;
+                    im[a] += t_im;
+                }
+            }
+            len <<= 1;
+        }
+    }
+}
+
+// Short-time spectrum of the waveform buffer, computed incrementally as the buffer grows. Each
+// frame holds one level (0..=255 across FLOOR_DB..0 dBFS) per frequency bin.
+pub struct Spectrogram {
+    frames: Vec<[u8; BINS]>,
+    fft: Fft,
+    // Rate of the buffer the frames came from; a change means it was decimated and must be redone
+    sample_rate: f32,
+}
+
+impl Default for Spectrogram {
+    fn default() -> Self {
+        Self {
+            frames: Vec::new(),
+            fft: Fft::new(FFT_SIZE),
+            sample_rate: 0.0,
+        }
+    }
+}
+
+impl Spectrogram {
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.sample_rate = 0.0;
+    }
+
+    // Analyses the part of `samples` not covered yet. The buffer is expected to only grow;
+    // if it shrank or changed rate it is analysed again from the start.
+    pub fn update(&mut self, samples: &[f32], sample_rate: f32) {
+        if sample_rate != self.sample_rate || samples.len() < self.frames.len() * HOP_SIZE {
+            self.frames.clear();
+            self.sample_rate = sample_rate;
+        }
+
+        for _ in 0..MAX_FRAMES_PER_UPDATE {
+            let start = self.frames.len() * HOP_SIZE;
+            let Some(block) = samples.get(start..start + FFT_SIZE) else {
+                break;
+            };
+
+            let mut frame = [0u8; BINS];
+            for (level, db) in frame.iter_mut().zip(self.fft.magnitudes_db(block)) {
+                *level = (((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0) * 255.0) as u8;
+            }
+            self.frames.push(frame);
+        }
+    }
+
+    // Frames starting between sample `start` and `end`, along with the index of the first one.
+    // Frame `i` starts at sample `i * HOP_SIZE`.
+    pub fn frames(&self, start: usize, end: usize) -> (usize, &[[u8; BINS]]) {
+        let first = (start / HOP_SIZE).min(self.frames.len());
+        let last = (end / HOP_SIZE).clamp(first, self.frames.len());
+        (first, &self.frames[first..last])
+    }
+}
+
+// Samples analysed per update of the live spectrum
+pub const ANALYZER_SIZE: usize = 2048;
+pub const ANALYZER_BANDS: usize = 32;
+// Lower edge of the first band; bands are spaced logarithmically up to the Nyquist frequency
+const LOWEST_BAND_HZ: f32 = 30.0;
+// Fall rates of the bars and of the held peaks, in dB per second, and how long a peak holds
+const BAR_FALL_DB_PER_SEC: f32 = 60.0;
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+const PEAK_HOLD_SECS: f32 = 0.6;
+
+// Live bar-graph spectrum with falling bars and peak hold, fed a block around the playhead each frame
+pub struct SpectrumAnalyzer {
+    fft: Fft,
+    // Current bar and held peak levels in dBFS
+    bands: [f32; ANALYZER_BANDS],
+    peaks: [f32; ANALYZER_BANDS],
+    // Seconds since each peak was last pushed up
+    peak_ages: [f32; ANALYZER_BANDS],
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self {
+            fft: Fft::new(ANALYZER_SIZE),
+            bands: [FLOOR_DB; ANALYZER_BANDS],
+            peaks: [FLOOR_DB; ANALYZER_BANDS],
+            peak_ages: [0.0; ANALYZER_BANDS],
+        }
+    }
+}
+
+impl SpectrumAnalyzer {
+    // Advances the display by `elapsed_secs`. Without a block (paused, or no audio decoded yet)
+    // the bars fall back towards silence.
+    pub fn update(&mut self, block: Option<&[f32]>, sample_rate: f32, elapsed_secs: f32) {
+        let levels = match block {
+            Some(block) if block.len() == ANALYZER_SIZE && sample_rate > 0.0 => {
+                self.band_levels(&self.fft.magnitudes_db(block), sample_rate)
+            }
+            _ => [FLOOR_DB; ANALYZER_BANDS],
+        };
+
+        let bands = self.bands.iter_mut().zip(&mut self.peaks).zip(&mut self.peak_ages);
+        for (((bar, peak), peak_age), level) in bands.zip(levels) {
+            *bar = level.max(*bar - BAR_FALL_DB_PER_SEC * elapsed_secs).max(FLOOR_DB);
+
+            if *bar >= *peak {
+                *peak = *bar;
+                *peak_age = 0.0;
+            } else {
+                *peak_age += elapsed_secs;
+                if *peak_age > PEAK_HOLD_SECS {
+                    *peak = (*peak - PEAK_FALL_DB_PER_SEC * elapsed_secs).max(*bar);
+                }
+            }
+        }
+    }
+
+    // Bar and peak heights as fractions (0..1) of the range from FLOOR_DB to 0 dBFS
+    pub fn levels(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        let normalize = |db: f32| ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        self.bands.iter().zip(&self.peaks).map(move |(&band, &peak)| (normalize(band), normalize(peak)))
+    }
+
+    // Loudest bin within each band. Low bands narrower than one bin take the bin they fall in.
+    fn band_levels(&self, bins: &[f32], sample_rate: f32) -> [f32; ANALYZER_BANDS] {
+        let nyquist = sample_rate / 2.0;
+        let bin_hz = nyquist / bins.len() as f32;
+        let ratio = (nyquist / LOWEST_BAND_HZ).powf(1.0 / ANALYZER_BANDS as f32);
+
+        let mut levels = [FLOOR_DB; ANALYZER_BANDS];
+        for (band, level) in levels.iter_mut().enumerate() {
+            let low_hz = LOWEST_BAND_HZ * ratio.powi(band as i32);
+            let high_hz = low_hz * ratio;
+            let first = ((low_hz / bin_hz) as usize).min(bins.len() - 1);
+            let last = ((high_hz / bin_hz) as usize).clamp(first + 1, bins.len());
+            *level = bins[first..last].iter().copied().fold(FLOOR_DB, f32::max);
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scale_sine_reads_zero_dbfs_in_its_bin() {
+        const BIN: usize = 64;
+        let fft = Fft::new(FFT_SIZE);
+        let block: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (2.0 * PI * BIN as f32 * i as f32 / FFT_SIZE as f32).sin())
+            .collect();
+        let levels = fft.magnitudes_db(&block);
+
+        assert_eq!(levels.len(), BINS);
+        assert!(levels[BIN].abs() < 0.1, "bin {} reads {} dBFS", BIN, levels[BIN]);
+        // The Hann window spreads the tone into the neighbouring bins and no further
+        for (bin, &level) in levels.iter().enumerate() {
+            if bin.abs_diff(BIN) > 1 {
+                assert!(level < FLOOR_DB + 10.0, "bin {} leaks {} dBFS", bin, level);
+            }
+        }
+    }
+}
@@ -1,19 +1,331 @@
+use crate::audio::waveform_cache::{self, CachedWaveform};
+use crate::utils::metadata::codec_duration;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fs::File;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::f32::consts::PI;
+use std::sync::{Arc, LazyLock};
 use std::thread;
+use std::time::Duration;
 use symphonia::core::audio::{AudioBufferRef, Signal};
+use symphonia::core::codecs::Decoder;
+use symphonia::core::formats::FormatReader;
 use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
 use symphonia::core::errors::Error;
 use symphonia::default::{get_codecs, get_probe};
 
+// Messages sent from the decode thread to the generator
+enum WaveformMsg {
+    SampleRate(u32),
+    // Track length from the container, sent before decoding starts when it is known
+    Duration(Duration),
+    Genre(String),
+    // Fraction of the track decoded so far, sent only when the length is known
+    Progress(f32),
+    Chunk(WaveformChunk),
+    // The track's overview from the disk cache, sent ahead of the decode
+    Cached(CachedWaveform),
+    // The file couldn't be opened or decoded at all
+    Failed(String),
+}
+
+// Decoded samples for a run of frames; left/right are empty for mono sources
+struct WaveformChunk {
+    mono: Vec<f32>,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl WaveformChunk {
+    // Linearly resamples the chunk from one sample rate to another
+    fn resampled(self, from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            mono: resample(&self.mono, from_rate, to_rate),
+            left: resample(&self.left, from_rate, to_rate),
+            right: resample(&self.right, from_rate, to_rate),
+        }
+    }
+
+    fn is_stereo(&self) -> bool {
+        !self.left.is_empty()
+    }
+
+    fn append(&mut self, other: WaveformChunk) {
+        self.mono.extend(other.mono);
+        self.left.extend(other.left);
+        self.right.extend(other.right);
+    }
+}
+
+// Target size of the overview saved to the disk cache
+const OVERVIEW_BUCKETS: usize = 4096;
+
+// (min, max) overview of a stream of unknown length. Neighbouring pairs are merged whenever it
+// reaches twice the target size, so it ends up with between one and two times OVERVIEW_BUCKETS.
+struct OverviewBuilder {
+    pairs: Vec<(f32, f32)>,
+    // Samples per pair, and the pair being filled
+    bucket_len: usize,
+    current: (f32, f32),
+    in_current: usize,
+}
+
+impl Default for OverviewBuilder {
+    fn default() -> Self {
+        Self {
+            pairs: Vec::new(),
+            bucket_len: 1,
+            current: (f32::MAX, f32::MIN),
+            in_current: 0,
+        }
+    }
+}
+
+impl OverviewBuilder {
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.current = (self.current.0.min(sample), self.current.1.max(sample));
+            self.in_current += 1;
+            if self.in_current == self.bucket_len {
+                self.pairs.push(self.current);
+                self.current = (f32::MAX, f32::MIN);
+                self.in_current = 0;
+                if self.pairs.len() == 2 * OVERVIEW_BUCKETS {
+                    self.pairs = merge_pairs(&self.pairs, OVERVIEW_BUCKETS);
+                    self.bucket_len *= 2;
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<(f32, f32)> {
+        if self.in_current > 0 {
+            self.pairs.push(self.current);
+        }
+        self.pairs
+    }
+}
+
+// Reduces (min, max) pairs to `buckets` pairs covering the same span
+fn merge_pairs(pairs: &[(f32, f32)], buckets: usize) -> Vec<(f32, f32)> {
+    if pairs.is_empty() || buckets == 0 {
+        return Vec::new();
+    }
+    (0..buckets)
+        .map(|bucket| {
+            let start = (bucket * pairs.len() / buckets).min(pairs.len() - 1);
+            let end = ((bucket + 1) * pairs.len() / buckets).clamp(start + 1, pairs.len());
+            pairs[start..end]
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), &(low, high)| (min.min(low), max.max(high)))
+        })
+        .collect()
+}
+
+fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if samples.is_empty() || from_rate == 0 {
+        return Vec::new();
+    }
+
+    let last = samples.len() - 1;
+    let out_len = ((samples.len() as u64 * to_rate as u64) / from_rate as u64).max(1) as usize;
+    let step = from_rate as f64 / to_rate as f64;
+
+    (0..out_len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = (position as usize).min(last);
+            let frac = (position - index as f64) as f32;
+            let next = samples[(index + 1).min(last)];
+            samples[index] + (next - samples[index]) * frac
+        })
+        .collect()
+}
+
+// How stored samples are shaped for display
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeMode {
+    // Raw samples, showing every transient
+    #[default]
+    Peak,
+    // Moving RMS level, a smoother curve that tracks perceived loudness
+    Rms,
+}
+
+impl EnvelopeMode {
+    pub const ALL: [EnvelopeMode; 2] = [Self::Peak, Self::Rms];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Peak => "Peak",
+            Self::Rms => "RMS",
+        }
+    }
+}
+
+// Length of the moving window used for the RMS envelope
+const RMS_WINDOW_SECS: f32 = 0.02;
+
+// How sample amplitudes map to height on screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveformScale {
+    #[default]
+    Linear,
+    // Level in dB above DECIBEL_FLOOR, so quiet passages keep a visible shape
+    Decibel,
+}
+
+impl WaveformScale {
+    pub const ALL: [WaveformScale; 2] = [Self::Linear, Self::Decibel];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Decibel => "Decibel",
+        }
+    }
+}
+
+// Level drawn as zero height on the decibel scale
+const DECIBEL_FLOOR: f32 = -60.0;
+
+// Default batching for chunks sent from the worker
+const DEFAULT_CHUNK_MS: u32 = 50;
+
+// Default ceiling for waveform buffer memory
+const DEFAULT_MEMORY_CAP: usize = 256 * 1024 * 1024;
+
 pub struct WaveformGenerator {
     // Optional channel receiver to fetch waveform chunks
-    receiver: Option<Receiver<(Option<u32>, Vec<f32>)>>,
+    receiver: Option<Receiver<WaveformMsg>>,
+    // Tells the current worker to stop decoding once its output is no longer wanted
+    cancel: Arc<AtomicBool>,
 
     // Buffer to store the waveform data
     buffer: Vec<f32>,
+    // Per-channel buffers for the first two channels, empty for mono files
+    left: Vec<f32>,
+    right: Vec<f32>,
     // The audio sample rate (e.g., 44100 Hz)
     sample_rate: u32,
+    // Upper bound on the memory used by the stored buffers, in bytes
+    memory_cap: usize,
+    // How many source frames each stored sample represents; grows by doubling to stay under the cap
+    decimation: usize,
+    decimators: [Decimator; 3],
+    // Sample counts per level bin, filled in once generation has finished
+    histogram: Vec<u32>,
+    // RMS level of the whole track in dBFS, known once generation has finished
+    loudness_db: Option<f32>,
+    // Genre tag from the file's metadata, if it has one
+    genre: Option<String>,
+    // Decoding progress of the current generation, None when the length is unknown
+    progress: Option<f32>,
+    // Audio the worker accumulates before sending a chunk, in milliseconds; 0 sends every packet
+    chunk_ms: u32,
+    // Level below which audio counts as silence, and the silent gaps found with it (start, end
+    // in seconds) once generation has finished
+    silence_threshold_db: f32,
+    silent_gaps: Vec<(f32, f32)>,
+    envelope_mode: EnvelopeMode,
+    scale: WaveformScale,
+    // Why the last generation failed, until the UI picks it up
+    error: Option<String>,
+    // Largest absolute sample stored so far, for scaling the display to fill its height
+    peak: f32,
+    // Length of the current track as declared by its container
+    duration: Option<Duration>,
+    // Overview from the disk cache, drawn in place of the buffer's own while the decode runs
+    cached: Option<CachedWaveform>,
+}
+
+// Shortest quiet stretch treated as a gap between songs, and the block size used to find them
+const MIN_GAP_SECS: f32 = 0.5;
+const GAP_BLOCK_SECS: f32 = 0.01;
+
+// Range and resolution of the level histogram; quieter samples are counted in the lowest bin
+const HISTOGRAM_FLOOR_DB: f32 = -60.0;
+pub const HISTOGRAM_BIN_DB: f32 = 3.0;
+
+// Length of the low-pass applied before each halving; odd so the filter has a centre tap and
+// adds no delay
+const HALFBAND_TAPS: usize = 31;
+const HALFBAND_HALF: usize = HALFBAND_TAPS / 2;
+
+// Blackman-windowed sinc with its cutoff at a quarter of the input rate, i.e. the Nyquist
+// frequency of the halved output, normalized to unity gain at DC
+static HALFBAND: LazyLock<[f32; HALFBAND_TAPS]> = LazyLock::new(|| {
+    let mut taps = [0f32; HALFBAND_TAPS];
+    for (i, tap) in taps.iter_mut().enumerate() {
+        let n = i as f32 - HALFBAND_HALF as f32;
+        let sinc = if n == 0.0 { 1.0 } else { (PI * n / 2.0).sin() / (PI * n / 2.0) };
+        let phase = 2.0 * PI * i as f32 / (HALFBAND_TAPS - 1) as f32;
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+        *tap = sinc * window;
+    }
+    let sum: f32 = taps.iter().sum();
+    taps.map(|tap| tap / sum)
+});
+
+// One halving step: low-passes a stream and keeps every other sample, so what's stored is still
+// band-limited audio at the lower rate rather than an aliased pick of samples
+#[derive(Clone)]
+struct HalfbandStage {
+    // Input from the start of the next output's window onwards
+    history: Vec<f32>,
+}
+
+impl Default for HalfbandStage {
+    fn default() -> Self {
+        // The first window is centred on the first sample, with silence before it
+        Self {
+            history: vec![0.0; HALFBAND_HALF],
+        }
+    }
+}
+
+impl HalfbandStage {
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut input = std::mem::take(&mut self.history);
+        input.extend_from_slice(samples);
+
+        let mut out = Vec::with_capacity(input.len() / 2);
+        let mut start = 0;
+        while start + HALFBAND_TAPS <= input.len() {
+            let window = &input[start..start + HALFBAND_TAPS];
+            let sample: f32 = window.iter().zip(HALFBAND.iter()).map(|(s, tap)| s * tap).sum();
+            out.push(sample.clamp(-1.0, 1.0));
+            start += 2;
+        }
+
+        self.history = input.split_off(start.min(input.len()));
+        out
+    }
+}
+
+// Chain of halving steps applied to incoming samples; the decimation factor is 2^stages
+#[derive(Clone, Default)]
+struct Decimator {
+    stages: Vec<HalfbandStage>,
+}
+
+impl Decimator {
+    fn process(&mut self, samples: Vec<f32>) -> Vec<f32> {
+        self.stages.iter_mut().fold(samples, |samples, stage| stage.process(&samples))
+    }
+}
+
+// Halves a stored buffer, returning the step that continues the stream from where it ends
+fn halve(samples: &mut Vec<f32>) -> HalfbandStage {
+    let mut stage = HalfbandStage::default();
+    *samples = stage.process(samples);
+    stage
 }
 
 // Default implementation to initialize WaveformGenerator with default values
@@ -21,8 +333,27 @@ impl Default for WaveformGenerator {
     fn default() -> Self {
         Self {
             receiver: None,
+            cancel: Arc::new(AtomicBool::new(false)),
             buffer: Vec::new(),
+            left: Vec::new(),
+            right: Vec::new(),
             sample_rate: 44100,
+            memory_cap: DEFAULT_MEMORY_CAP,
+            decimation: 1,
+            decimators: Default::default(),
+            histogram: Vec::new(),
+            loudness_db: None,
+            genre: None,
+            progress: None,
+            chunk_ms: DEFAULT_CHUNK_MS,
+            silence_threshold_db: -50.0,
+            silent_gaps: Vec::new(),
+            envelope_mode: EnvelopeMode::default(),
+            scale: WaveformScale::default(),
+            error: None,
+            peak: 0.0,
+            duration: None,
+            cached: None,
         }
     }
 }
@@ -31,26 +362,253 @@ impl WaveformGenerator {
     // Starts the generation process for the waveform by clearing the buffer and spawning a thread
     pub fn generate_for(&mut self, file_path: &str) {
         self.buffer.clear();
+        self.left.clear();
+        self.right.clear();
+        self.decimation = 1;
+        self.decimators = Default::default();
+        self.histogram.clear();
+        self.loudness_db = None;
+        self.genre = None;
+        self.progress = None;
+        self.error = None;
+        self.duration = None;
+        self.peak = 0.0;
+        self.cached = None;
+        self.silent_gaps.clear();
+        // The previous worker would otherwise keep decoding until its next send fails
+        self.cancel.store(true, Ordering::Relaxed);
+        self.cancel = Arc::new(AtomicBool::new(false));
+
         let (tx, rx) = channel();
         self.receiver = Some(rx);
 
         let file_path = file_path.to_string();
+        let chunk_ms = self.chunk_ms;
+        let cache_params = Self::cache_params();
+        let cancel = self.cancel.clone();
         thread::spawn(move || {
-            Self::load_waveform_streaming(file_path, tx);
+            Self::load_waveform_streaming(file_path, chunk_ms, cache_params, &cancel, tx);
         });
     }
 
+    // What shapes a cached overview besides the cache format itself. The overview is built from
+    // the full-rate signal, so the memory cap and chunk size don't change it; only the number of
+    // peaks does. Entries made with another count are treated as misses.
+    fn cache_params() -> u64 {
+        OVERVIEW_BUCKETS as u64
+    }
+
+    // Sets how much audio the worker batches per message. Larger chunks cost less CPU but make
+    // the waveform fill in more coarsely. Applies from the next generation.
+    pub fn set_chunk_ms(&mut self, chunk_ms: u32) {
+        self.chunk_ms = chunk_ms;
+    }
+
     // Updates the buffer with data received on the channel
     pub fn update_buffer(&mut self) {
         if let Some(receiver) = &mut self.receiver {
-            let received_data: Vec<_> = receiver.try_iter().collect();
-            for (rate_opt, chunk) in received_data {
-                if let Some(rate) = rate_opt {
-                    self.set_sample_rate(rate);
+            let mut received_data = Vec::new();
+            let mut finished = false;
+            loop {
+                match receiver.try_recv() {
+                    Ok(msg) => received_data.push(msg),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+
+            for msg in received_data {
+                match msg {
+                    WaveformMsg::SampleRate(rate) => self.set_sample_rate(rate),
+                    WaveformMsg::Duration(duration) => self.duration = Some(duration),
+                    WaveformMsg::Genre(genre) => self.genre = Some(genre),
+                    WaveformMsg::Progress(progress) => self.progress = Some(progress),
+                    WaveformMsg::Chunk(chunk) => self.append_chunk(chunk),
+                    WaveformMsg::Cached(cached) => self.apply_cached(cached),
+                    WaveformMsg::Failed(message) => self.error = Some(message),
                 }
-                self.buffer.extend(chunk);
             }
+
+            // The worker drops its sender when the whole file has been decoded
+            if finished {
+                self.receiver = None;
+                self.histogram = Self::compute_histogram(&self.buffer);
+                self.loudness_db = Self::compute_loudness(&self.buffer);
+                self.silent_gaps = self.find_silent_gaps();
+            }
+        }
+    }
+
+    // Counts samples per level bin, from the loudest (0 dBFS) bin down to the floor
+    fn compute_histogram(samples: &[f32]) -> Vec<u32> {
+        let bins = (-HISTOGRAM_FLOOR_DB / HISTOGRAM_BIN_DB).ceil() as usize;
+        let mut histogram = vec![0u32; bins];
+        for &sample in samples {
+            let db = 20.0 * sample.abs().max(1e-9).log10();
+            let bin = ((-db / HISTOGRAM_BIN_DB).max(0.0) as usize).min(bins - 1);
+            histogram[bin] += 1;
+        }
+        histogram
+    }
+
+    // Overall RMS level in dBFS. This is a rough stand-in for integrated loudness: it has no
+    // frequency weighting and is measured on the stored (possibly decimated) samples.
+    fn compute_loudness(samples: &[f32]) -> Option<f32> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mean_square = samples.iter().map(|s| (s * s) as f64).sum::<f64>() / samples.len() as f64;
+        Some(10.0 * mean_square.max(1e-12).log10() as f32)
+    }
+
+    // Takes the reason the last generation failed, if it did
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    // Track length read from the container, once the worker has sent it. Files that don't
+    // declare their length never get one.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    // Takes what the cached overview knows about the track ahead of the decode
+    fn apply_cached(&mut self, cached: CachedWaveform) {
+        self.duration = self.duration.or(cached.duration);
+        if self.genre.is_none() {
+            self.genre = cached.genre.clone();
+        }
+        self.peak = self.peak.max(cached.peak);
+        self.cached = Some(cached);
+    }
+
+    // True while the worker is still decoding the current file
+    pub fn is_generating(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // Fraction (0..1) of the file decoded so far, or None for streams of unknown length
+    pub fn generation_progress(&self) -> Option<f32> {
+        self.progress
+    }
+
+    pub fn get_loudness_db(&self) -> Option<f32> {
+        self.loudness_db
+    }
+
+    pub fn get_genre(&self) -> Option<&str> {
+        self.genre.as_deref()
+    }
+
+    // Level distribution of the track, or None while the waveform is still being generated
+    pub fn get_histogram(&self) -> Option<&[u32]> {
+        if self.histogram.is_empty() {
+            None
+        } else {
+            Some(&self.histogram)
+        }
+    }
+
+    // Appends a chunk, keeping the per-channel buffers aligned with the mono buffer even if the
+    // stream switches between mono and stereo part-way through
+    fn append_chunk(&mut self, chunk: WaveformChunk) {
+        if self.buffer.is_empty() {
+            self.plan_decimation(chunk.is_stereo());
         }
+
+        let [mono_decimator, left_decimator, right_decimator] = &mut self.decimators;
+        // Channels that start part-way through pick up the filter state of the mono stream they
+        // are backfilled from
+        if chunk.is_stereo() && self.left.is_empty() {
+            *left_decimator = mono_decimator.clone();
+            *right_decimator = mono_decimator.clone();
+        }
+        let chunk = WaveformChunk {
+            mono: mono_decimator.process(chunk.mono),
+            left: left_decimator.process(chunk.left),
+            right: right_decimator.process(chunk.right),
+        };
+
+        self.peak = [&chunk.mono, &chunk.left, &chunk.right]
+            .into_iter()
+            .flatten()
+            .fold(self.peak, |peak, sample| peak.max(sample.abs()));
+
+        let chunk_is_stereo = !chunk.left.is_empty();
+        let have_stereo = !self.left.is_empty();
+
+        if chunk_is_stereo && !have_stereo && !self.buffer.is_empty() {
+            self.left.extend_from_slice(&self.buffer);
+            self.right.extend_from_slice(&self.buffer);
+        }
+
+        if chunk_is_stereo {
+            self.left.extend(chunk.left);
+            self.right.extend(chunk.right);
+        } else if have_stereo {
+            self.left.extend_from_slice(&chunk.mono);
+            self.right.extend_from_slice(&chunk.mono);
+        }
+
+        self.buffer.extend(chunk.mono);
+        self.enforce_memory_cap();
+    }
+
+    // Sets the memory ceiling in bytes; long files degrade to a coarser resolution instead of growing unbounded
+    pub fn set_memory_cap(&mut self, bytes: usize) {
+        self.memory_cap = bytes.max(1024 * 1024);
+        self.enforce_memory_cap();
+    }
+
+    fn memory_used(&self) -> usize {
+        (self.buffer.len() + self.left.len() + self.right.len()) * std::mem::size_of::<f32>()
+    }
+
+    // Picks the decimation for a track of known length before its first samples are stored, so
+    // it never has to be halved mid-way, and reserves the buffers so they don't overshoot the cap
+    // by growing in steps. Tracks of unknown length start at full rate and halve as they grow.
+    fn plan_decimation(&mut self, stereo: bool) {
+        let Some(duration) = self.duration else {
+            return;
+        };
+        let buffers = if stereo { 3 } else { 1 };
+        let frames = (duration.as_secs_f64() * self.sample_rate as f64) as usize;
+        while frames / self.decimation * buffers * std::mem::size_of::<f32>() > self.memory_cap {
+            self.add_decimation_stage();
+        }
+
+        let expected = frames / self.decimation + 1;
+        self.buffer.reserve_exact(expected);
+        if stereo {
+            self.left.reserve_exact(expected);
+            self.right.reserve_exact(expected);
+        }
+    }
+
+    fn add_decimation_stage(&mut self) {
+        for decimator in &mut self.decimators {
+            decimator.stages.push(HalfbandStage::default());
+        }
+        self.decimation *= 2;
+    }
+
+    fn enforce_memory_cap(&mut self) {
+        while self.memory_used() > self.memory_cap && self.buffer.len() > 1 {
+            let buffers = [&mut self.buffer, &mut self.left, &mut self.right];
+            for (samples, decimator) in buffers.into_iter().zip(&mut self.decimators) {
+                decimator.stages.push(halve(samples));
+                samples.shrink_to_fit();
+            }
+            self.decimation *= 2;
+        }
+    }
+
+    // Rate of the stored buffers, accounting for any decimation applied to respect the memory cap
+    pub fn samples_per_second(&self) -> f32 {
+        self.get_sample_rate() as f32 / self.decimation as f32
     }
 
     // Sets the sample rate of the waveform
@@ -68,190 +626,784 @@ impl WaveformGenerator {
         &self.buffer
     }
 
+    pub fn set_envelope_mode(&mut self, mode: EnvelopeMode) {
+        self.envelope_mode = mode;
+    }
+
+    pub fn set_scale(&mut self, scale: WaveformScale) {
+        self.scale = scale;
+    }
+
+    // Shapes a slice of stored samples for display according to the envelope mode and scale.
+    // Analysis always works on the raw buffers; only what's drawn is transformed.
+    pub fn display_samples<'a>(&self, samples: &'a [f32]) -> Cow<'a, [f32]> {
+        let samples = self.envelope(samples);
+        match self.scale {
+            WaveformScale::Linear => samples,
+            WaveformScale::Decibel => Cow::Owned(
+                samples
+                    .iter()
+                    .map(|&sample| {
+                        let db = 20.0 * sample.abs().max(f32::MIN_POSITIVE).log10();
+                        sample.signum() * ((db - DECIBEL_FLOOR) / -DECIBEL_FLOOR).clamp(0.0, 1.0)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn envelope<'a>(&self, samples: &'a [f32]) -> Cow<'a, [f32]> {
+        match self.envelope_mode {
+            EnvelopeMode::Peak => Cow::Borrowed(samples),
+            EnvelopeMode::Rms => {
+                let half_window = ((RMS_WINDOW_SECS * self.samples_per_second()) as usize / 2).max(1);
+
+                // Prefix sums of squares give each window's energy in constant time
+                let mut energy = Vec::with_capacity(samples.len() + 1);
+                energy.push(0f64);
+                for &sample in samples {
+                    energy.push(energy[energy.len() - 1] + (sample as f64) * (sample as f64));
+                }
+
+                let rms = (0..samples.len())
+                    .map(|i| {
+                        let start = i.saturating_sub(half_window);
+                        let end = (i + half_window).min(samples.len());
+                        ((energy[end] - energy[start]) / (end - start) as f64).sqrt() as f32
+                    })
+                    .collect();
+                Cow::Owned(rms)
+            }
+        }
+    }
+
+    // The cached overview while the track is still decoding, if the disk cache had one
+    fn cached_overview(&self) -> Option<&CachedWaveform> {
+        self.cached.as_ref().filter(|_| self.is_generating())
+    }
+
+    // Reduces the whole buffer to `buckets` (min, max) pairs for a full-track overview. While
+    // generation is running this covers only what has been decoded so far, unless the disk cache
+    // had the whole track.
+    pub fn get_overview(&self, buckets: usize) -> Vec<(f32, f32)> {
+        if let Some(cached) = self.cached_overview() {
+            return merge_pairs(&cached.overview, buckets);
+        }
+        if self.buffer.is_empty() || buckets == 0 {
+            return Vec::new();
+        }
+
+        let samples_per_bucket = self.buffer.len() as f32 / buckets as f32;
+        (0..buckets)
+            .map(|bucket| {
+                let start = (bucket as f32 * samples_per_bucket) as usize;
+                let end = (((bucket + 1) as f32 * samples_per_bucket) as usize).clamp(start + 1, self.buffer.len());
+                self.buffer[start.min(self.buffer.len() - 1)..end]
+                    .iter()
+                    .fold((f32::MAX, f32::MIN), |(min, max), &s| (min.min(s), max.max(s)))
+            })
+            .collect()
+    }
+
+    // Seconds of audio get_overview covers
+    pub fn overview_secs(&self) -> f32 {
+        match self.cached_overview() {
+            Some(cached) => cached.secs,
+            None => self.buffer.len() as f32 / self.samples_per_second().max(1.0),
+        }
+    }
+
+    // Retrieves the left and right channel buffers, or None for mono files
+    pub fn get_channel_buffers(&self) -> Option<(&[f32], &[f32])> {
+        if self.left.is_empty() {
+            None
+        } else {
+            Some((&self.left, &self.right))
+        }
+    }
+
+    // Sets the silence level used for gap navigation, recomputing gaps for a finished waveform
+    pub fn set_silence_threshold_db(&mut self, threshold_db: f32) {
+        self.silence_threshold_db = threshold_db;
+        if !self.is_generating() {
+            self.silent_gaps = self.find_silent_gaps();
+        }
+    }
+
+    fn find_silent_gaps(&self) -> Vec<(f32, f32)> {
+        let rate = self.samples_per_second();
+        let block = ((GAP_BLOCK_SECS * rate) as usize).max(1);
+        let threshold = 10f32.powf(self.silence_threshold_db / 20.0);
+
+        let mut gaps = Vec::new();
+        let mut gap_start: Option<usize> = None;
+        for (index, chunk) in self.buffer.chunks(block).enumerate() {
+            let silent = chunk.iter().all(|s| s.abs() < threshold);
+            match (silent, gap_start) {
+                (true, None) => gap_start = Some(index * block),
+                (false, Some(start)) => {
+                    gaps.push((start, index * block));
+                    gap_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = gap_start {
+            gaps.push((start, self.buffer.len()));
+        }
+
+        gaps.into_iter()
+            .map(|(start, end)| (start as f32 / rate, end as f32 / rate))
+            .filter(|(start, end)| end - start >= MIN_GAP_SECS)
+            .collect()
+    }
+
+    // End of the first silent gap after `position_secs`, i.e. where the next section starts
+    pub fn next_gap_end(&self, position_secs: f32) -> Option<f32> {
+        self.silent_gaps.iter().map(|&(_, end)| end).find(|&end| end > position_secs)
+    }
+
+    // End of the last silent gap before `position_secs`
+    pub fn previous_gap_end(&self, position_secs: f32) -> Option<f32> {
+        self.silent_gaps.iter().rev().map(|&(_, end)| end).find(|&end| end < position_secs)
+    }
+
+    // Time in seconds of the largest absolute sample
+    pub fn loudest_point(&self) -> Option<f32> {
+        let (index, _) = self
+            .buffer
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?;
+        Some(index as f32 / self.samples_per_second())
+    }
+
+    // Start time in seconds of the `window_secs` stretch with the highest energy, for previews
+    pub fn loudest_section(&self, window_secs: f32) -> Option<f32> {
+        let samples = &self.buffer;
+        let window = ((window_secs * self.samples_per_second()) as usize).max(1);
+        if samples.is_empty() {
+            return None;
+        }
+        if samples.len() <= window {
+            return Some(0.0);
+        }
+
+        let mut energy: f64 = samples[..window].iter().map(|s| (s * s) as f64).sum();
+        let (mut best_energy, mut best_start) = (energy, 0);
+        for start in 1..=samples.len() - window {
+            let (leaving, entering) = (samples[start - 1], samples[start + window - 1]);
+            energy += (entering * entering) as f64 - (leaving * leaving) as f64;
+            if energy > best_energy {
+                best_energy = energy;
+                best_start = start;
+            }
+        }
+        Some(best_start as f32 / self.samples_per_second())
+    }
+
+    // Largest absolute sample of the track decoded so far
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    // `len` mixed samples centered on `position_secs`, or None when that much isn't decoded there
+    pub fn block_at(&self, position_secs: f32, len: usize) -> Option<&[f32]> {
+        let center = (position_secs * self.samples_per_second()) as usize;
+        let start = center.saturating_sub(len / 2);
+        self.buffer.get(start..start + len)
+    }
+
+    // Largest absolute sample in a window centered on `position_secs`, across all channels
+    pub fn peak_at(&self, position_secs: f32, window_secs: f32) -> Option<f32> {
+        let center = (position_secs * self.samples_per_second()) as usize;
+        let half_window = ((window_secs * self.samples_per_second()) as usize / 2).max(1);
+
+        let buffers: Vec<&[f32]> = match self.get_channel_buffers() {
+            Some((left, right)) => vec![left, right],
+            None => vec![&self.buffer],
+        };
+        buffers
+            .into_iter()
+            .filter_map(|samples| {
+                let start = center.saturating_sub(half_window).min(samples.len());
+                let end = (center + half_window).min(samples.len());
+                samples[start..end].iter().map(|s| s.abs()).reduce(f32::max)
+            })
+            .reduce(f32::max)
+    }
+
+    // Peak and RMS level of the left and right channel over a window centered on `position_secs`.
+    // Mono files report the same levels for both.
+    pub fn channel_levels_at(&self, position_secs: f32, window_secs: f32) -> Option<[(f32, f32); 2]> {
+        let center = (position_secs * self.samples_per_second()) as usize;
+        let half_window = ((window_secs * self.samples_per_second()) as usize / 2).max(1);
+        let levels = |samples: &[f32]| {
+            let start = center.saturating_sub(half_window).min(samples.len());
+            let end = (center + half_window).min(samples.len());
+            let window = &samples[start..end];
+            if window.is_empty() {
+                return None;
+            }
+            let peak = window.iter().map(|s| s.abs()).fold(0.0, f32::max);
+            let rms = (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt();
+            Some((peak, rms))
+        };
+
+        match self.get_channel_buffers() {
+            Some((left, right)) => Some([levels(left)?, levels(right)?]),
+            None => levels(&self.buffer).map(|level| [level, level]),
+        }
+    }
+
+    // Computes the L/R phase correlation (-1..+1) over a short window centered on `position_secs`
+    pub fn correlation_at(&self, position_secs: f32, window_secs: f32) -> Option<f32> {
+        let (left, right) = self.get_channel_buffers()?;
+        let len = left.len().min(right.len());
+
+        let center = (position_secs * self.samples_per_second()) as usize;
+        let half_window = ((window_secs * self.samples_per_second()) as usize / 2).max(1);
+        let start = center.saturating_sub(half_window).min(len);
+        let end = (center + half_window).min(len);
+
+        let (mut sum_lr, mut sum_ll, mut sum_rr) = (0f32, 0f32, 0f32);
+        for (l, r) in left[start..end].iter().zip(&right[start..end]) {
+            sum_lr += l * r;
+            sum_ll += l * l;
+            sum_rr += r * r;
+        }
+
+        let denominator = (sum_ll * sum_rr).sqrt();
+        if denominator <= f32::EPSILON {
+            return None; // Silence has no meaningful correlation
+        }
+        Some((sum_lr / denominator).clamp(-1.0, 1.0))
+    }
+
+    // The genre tagged in a metadata revision, unless it's blank
+    fn genre_tag(revision: &MetadataRevision) -> Option<String> {
+        revision
+            .tags()
+            .iter()
+            .find(|tag| tag.std_key == Some(StandardTagKey::Genre))
+            .map(|tag| tag.value.to_string())
+            .filter(|genre| !genre.trim().is_empty())
+    }
+
     // Loads the audio file in a streaming fashion and processes the waveform
-    fn load_waveform_streaming(file_path: String, tx: Sender<(Option<u32>, Vec<f32>)>) {
+    fn load_waveform_streaming(
+        file_path: String,
+        chunk_ms: u32,
+        cache_params: u64,
+        cancel: &AtomicBool,
+        tx: Sender<WaveformMsg>,
+    ) {
+        let fail = |message: String| {
+            let _ = tx.send(WaveformMsg::Failed(message));
+        };
+
+        // A cached overview fills in the whole track at once; the decode still runs for the detail
+        let cached = waveform_cache::load(&file_path, cache_params);
+        let from_cache = cached.is_some();
+        if let Some(cached) = cached
+            && tx.send(WaveformMsg::Cached(cached)).is_err()
+        {
+            return;
+        }
+
         let file = match File::open(&file_path) {
             Ok(f) => f,
-            Err(_) => return,
+            Err(e) => return fail(e.to_string()),
         };
 
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
+        let mut hint = Hint::new();
+        if let Some(extension) = Path::new(&file_path).extension() {
+            hint.with_extension(&extension.to_string_lossy());
+        }
+
         let probed = match get_probe().format(
-            &Default::default(),
+            &hint,
             mss,
             &Default::default(),
             &Default::default(),
         ) {
             Ok(p) => p,
-            Err(_) => return,
+            Err(e) => return fail(e.to_string()),
         };
 
         let mut format_reader = probed.format;
 
-        let (track_id, sample_rate) = {
-            // Limit the immutable borrow to this block to avoid conflicts
-            let track = match format_reader.default_track() {
-                Some(t) => t,
-                None => return,
-            };
+        // Tags may live in the container or ahead of it (e.g. ID3 in front of an MP3 stream)
+        let container_genre = format_reader.metadata().current().and_then(Self::genre_tag);
+        let genre = container_genre.or_else(|| {
+            let mut metadata = probed.metadata;
+            metadata.get().and_then(|m| m.current().and_then(Self::genre_tag))
+        });
+        if let Some(genre) = &genre {
+            let _ = tx.send(WaveformMsg::Genre(genre.clone()));
+        }
 
-            let rate = track.codec_params.sample_rate;
-            (track.id, rate)
+        let (mut track_id, total_frames, duration) = match format_reader.default_track() {
+            Some(t) => {
+                let duration = codec_duration(&t.codec_params);
+                if let Some(duration) = duration {
+                    let _ = tx.send(WaveformMsg::Duration(duration));
+                }
+                (t.id, t.codec_params.n_frames.filter(|&n| n > 0), duration)
+            }
+            None => return fail("File has no audio track".to_string()),
         };
 
-        let mut decoder = match get_codecs().make(&format_reader.tracks()[track_id as usize].codec_params, &Default::default()) {
-            Ok(d) => d,
-            Err(_) => return,
+        let mut decoder = match Self::make_decoder(format_reader.as_ref(), track_id) {
+            Some(d) => d,
+            None => return fail("Unsupported codec".to_string()),
         };
 
-        let mut sample_rate_sent = false;
+        // Rate every waveform sample is expressed in. Streams that switch rate mid-way (chained
+        // OGG, some Opus files) have later segments resampled to it so the time mapping holds.
+        let mut internal_rate: Option<u32> = None;
+
+        // Progress is reported in whole-percent steps to keep channel traffic low
+        let mut frames_decoded = 0u64;
+        let mut last_percent = 0u64;
+
+        // Decoded audio waiting to be sent as one chunk
+        let mut pending: Option<WaveformChunk> = None;
+
+        // Full-rate overview and peak for the disk cache
+        let mut overview = OverviewBuilder::default();
+        let mut peak = 0f32;
+        let mut samples_decoded = 0u64;
+        // Only a decode that ran to the end of the stream is cached as the whole track
+        let mut reached_end = false;
 
         loop {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+
             let packet = match format_reader.next_packet() {
                 Ok(p) => p,
-                Err(Error::IoError(_)) => break, // End of file or I/O error
+                Err(Error::ResetRequired) => {
+                    // A chained stream started a new segment and lists its tracks afresh, under new
+                    // ids; rebuild the decoder for the new segment's track
+                    let Some(track) = format_reader.default_track() else {
+                        break;
+                    };
+                    track_id = track.id;
+                    match Self::make_decoder(format_reader.as_ref(), track_id) {
+                        Some(d) => decoder = d,
+                        None => break,
+                    }
+                    continue;
+                }
+                Err(Error::IoError(err)) => {
+                    // Formats report the end of the stream as an unexpected EOF; anything else
+                    // cut the decode short
+                    reached_end = err.kind() == io::ErrorKind::UnexpectedEof;
+                    break;
+                }
                 Err(_) => continue,
             };
 
+            if packet.track_id() != track_id {
+                // Packets of other tracks alongside ours are skipped. Once our track is gone, the
+                // packet belongs to the track that replaced it in a new chained segment.
+                if format_reader.tracks().iter().any(|t| t.id == track_id) {
+                    continue;
+                }
+                track_id = packet.track_id();
+                match Self::make_decoder(format_reader.as_ref(), track_id) {
+                    Some(d) => decoder = d,
+                    None => break,
+                }
+            }
+
             match decoder.decode(&packet) {
                 Ok(audio_buffer) => {
-                    if !sample_rate_sent {
-                        if let Some(rate) = sample_rate {
-                            let empty_buf: Vec<f32> = Vec::new();
-                            if tx.send((Some(rate), empty_buf)).is_err() {
+                    frames_decoded += audio_buffer.frames() as u64;
+                    if let Some(total) = total_frames {
+                        let percent = (frames_decoded * 100 / total).min(100);
+                        if percent > last_percent {
+                            last_percent = percent;
+                            let _ = tx.send(WaveformMsg::Progress(percent as f32 / 100.0));
+                        }
+                    }
+
+                    let rate = audio_buffer.spec().rate;
+                    let target_rate = match internal_rate {
+                        Some(target) => target,
+                        None => {
+                            if tx.send(WaveformMsg::SampleRate(rate)).is_err() {
                                 break;
                             }
-                            sample_rate_sent = true;
+                            internal_rate = Some(rate);
+                            rate
                         }
+                    };
+
+                    let mut chunk_waveform = Self::process_audio_buffer(audio_buffer);
+                    if rate != target_rate {
+                        chunk_waveform = chunk_waveform.resampled(rate, target_rate);
                     }
 
-                    let chunk_waveform = Self::process_audio_buffer(audio_buffer);
-                    if !chunk_waveform.is_empty() {
-                        if tx.send((None, chunk_waveform)).is_err() {
-                            break; // Disconnected receiver
-                        }
+                    if chunk_waveform.mono.is_empty() {
+                        continue;
+                    }
+
+                    overview.push(&chunk_waveform.mono);
+                    samples_decoded += chunk_waveform.mono.len() as u64;
+                    peak = [&chunk_waveform.mono, &chunk_waveform.left, &chunk_waveform.right]
+                        .into_iter()
+                        .flatten()
+                        .fold(peak, |peak, sample| peak.max(sample.abs()));
+
+                    // A mono/stereo switch can't share a chunk with what came before it
+                    if let Some(batch) = pending.take_if(|batch| batch.is_stereo() != chunk_waveform.is_stereo())
+                        && tx.send(WaveformMsg::Chunk(batch)).is_err()
+                    {
+                        break; // Disconnected receiver
+                    }
+                    match &mut pending {
+                        Some(batch) => batch.append(chunk_waveform),
+                        None => pending = Some(chunk_waveform),
+                    }
+
+                    let target_len = (target_rate as u64 * chunk_ms as u64 / 1000) as usize;
+                    if let Some(batch) = pending.take_if(|batch| batch.mono.len() >= target_len)
+                        && tx.send(WaveformMsg::Chunk(batch)).is_err()
+                    {
+                        break; // Disconnected receiver
                     }
                 }
                 Err(Error::DecodeError(_)) => continue,
+                Err(Error::ResetRequired) => match Self::make_decoder(format_reader.as_ref(), track_id) {
+                    Some(d) => decoder = d,
+                    None => break,
+                },
                 Err(_) => break,
             }
         }
+
+        if let Some(batch) = pending {
+            let _ = tx.send(WaveformMsg::Chunk(batch));
+        }
+
+        if from_cache || !reached_end || samples_decoded == 0 || cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let cached = CachedWaveform {
+            duration,
+            genre,
+            secs: samples_decoded as f32 / internal_rate.unwrap_or(1) as f32,
+            peak,
+            overview: overview.finish(),
+        };
+        if let Err(err) = waveform_cache::store(&file_path, cache_params, &cached) {
+            eprintln!("Failed to cache waveform for {}: {}", file_path, err);
+        }
     }
 
-    // Converts the raw audio buffer into a uniform waveform vector
-    fn process_audio_buffer(audio_buffer: AudioBufferRef) -> Vec<f32> {
+    fn make_decoder(format_reader: &dyn FormatReader, track_id: u32) -> Option<Box<dyn Decoder>> {
+        let track = format_reader.tracks().iter().find(|t| t.id == track_id)?;
+        get_codecs().make(&track.codec_params, &Default::default()).ok()
+    }
+
+    // Converts the raw audio buffer into a uniform waveform chunk
+    fn process_audio_buffer(audio_buffer: AudioBufferRef) -> WaveformChunk {
         let channels = audio_buffer.spec().channels.count(); // Number of audio channels
         let frames = audio_buffer.frames(); // Number of audio frames
-        let mut chunk_waveform = Vec::with_capacity(frames); // Initialize a buffer for the waveform chunk
 
-        // Match the sample format of the audio buffer and process accordingly
+        // Match the sample format of the audio buffer and normalize each sample to [-1, 1]
         match audio_buffer {
             AudioBufferRef::U8(buf) => {
                 // Process unsigned 8-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += (buf.chan(ch)[frame] as f32 - 128.0) / 128.0; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32); // Average across channels
-                }
+                Self::collect_frames(channels, frames, |ch, frame| (buf.chan(ch)[frame] as f32 - 128.0) / 128.0)
             }
             AudioBufferRef::U16(buf) => {
                 // Process unsigned 16-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += (buf.chan(ch)[frame] as f32 - 32768.0) / 32768.0; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| (buf.chan(ch)[frame] as f32 - 32768.0) / 32768.0)
             }
             AudioBufferRef::U24(buf) => {
                 // Process unsigned 24-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        let sample = buf.chan(ch)[frame].inner() as i32 - 8_388_608;
-                        sum += (sample as f32) / 8_388_608.0; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| {
+                    let sample = buf.chan(ch)[frame].inner() as i32 - 8_388_608;
+                    sample as f32 / 8_388_608.0
+                })
             }
             AudioBufferRef::U32(buf) => {
                 // Process unsigned 32-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += (buf.chan(ch)[frame] as f32 - 2_147_483_648.0) / 2_147_483_648.0; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| {
+                    (buf.chan(ch)[frame] as f32 - 2_147_483_648.0) / 2_147_483_648.0
+                })
             }
             AudioBufferRef::S8(buf) => {
                 // Process signed 8-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += buf.chan(ch)[frame] as f32 / i8::MAX as f32; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame] as f32 / i8::MAX as f32)
             }
             AudioBufferRef::S16(buf) => {
                 // Process signed 16-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += buf.chan(ch)[frame] as f32 / i16::MAX as f32; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame] as f32 / i16::MAX as f32)
             }
             AudioBufferRef::S24(buf) => {
                 // Process signed 24-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        let sample = buf.chan(ch)[frame].inner();
-                        sum += sample as f32 / 8_388_608.0; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame].inner() as f32 / 8_388_608.0)
             }
             AudioBufferRef::S32(buf) => {
                 // Process signed 32-bit PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += buf.chan(ch)[frame] as f32 / i32::MAX as f32; // Normalize to [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame] as f32 / i32::MAX as f32)
             }
             AudioBufferRef::F32(buf) => {
-                // Process 32-bit floating point PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += buf.chan(ch)[frame]; // Already normalized in the range [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
-                }
+                // Process 32-bit floating point PCM samples, already normalized
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame])
             }
             AudioBufferRef::F64(buf) => {
-                // Process 64-bit floating point PCM samples
-                for frame in 0..frames {
-                    let mut sum = 0f32;
-                    for ch in 0..channels {
-                        sum += buf.chan(ch)[frame] as f32; // Already normalized in the range [-1, 1]
-                    }
-                    chunk_waveform.push(sum / channels as f32);
+                // Process 64-bit floating point PCM samples, already normalized
+                Self::collect_frames(channels, frames, |ch, frame| buf.chan(ch)[frame] as f32)
+            }
+        }
+    }
+
+    // Averages every frame across channels, keeping the first two channels separately for stereo analysis
+    fn collect_frames(channels: usize, frames: usize, sample_at: impl Fn(usize, usize) -> f32) -> WaveformChunk {
+        let stereo = channels >= 2;
+        let mut chunk = WaveformChunk {
+            mono: Vec::with_capacity(frames),
+            left: Vec::with_capacity(if stereo { frames } else { 0 }),
+            right: Vec::with_capacity(if stereo { frames } else { 0 }),
+        };
+
+        for frame in 0..frames {
+            let mut sum = 0f32;
+            for ch in 0..channels {
+                // Float formats can legitimately go past full scale; the waveform only draws to
+                // full scale, and the clip indicators still see a clamped over as a clip
+                let sample = sample_at(ch, frame).clamp(-1.0, 1.0);
+                if stereo && ch == 0 {
+                    chunk.left.push(sample);
+                } else if stereo && ch == 1 {
+                    chunk.right.push(sample);
                 }
+                sum += sample;
             }
+            chunk.mono.push((sum / channels.max(1) as f32).clamp(-1.0, 1.0)); // Average across channels
         }
 
-        chunk_waveform // Return the processed waveform chunk
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::flac_writer;
+    use symphonia::core::audio::{AudioBuffer, Channels, SignalSpec};
+    use std::fs;
+    use symphonia::core::sample::{u24, Sample};
+
+    fn sine(frequency: f32, rate: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|i| 0.5 * (2.0 * PI * frequency * i as f32 / rate).sin()).collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn halving_keeps_content_below_the_new_nyquist() {
+        let mut samples = sine(1000.0, 44100.0, 8192);
+        halve(&mut samples);
+        assert_eq!(samples.len(), 8192 / 2 - HALFBAND_HALF / 2);
+        assert!((peak(&samples[64..]) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn halving_removes_content_that_would_alias() {
+        // 18 kHz folds back to 4.05 kHz at 22.05 kHz unless it's filtered out first
+        let mut samples = sine(18000.0, 44100.0, 8192);
+        halve(&mut samples);
+        assert!(peak(&samples[64..]) < 0.01);
+    }
+
+    #[test]
+    fn streamed_halving_matches_halving_in_one_go() {
+        let samples = sine(440.0, 44100.0, 5000);
+        let mut whole = samples.clone();
+        halve(&mut whole);
+
+        let mut stage = HalfbandStage::default();
+        let streamed: Vec<f32> = samples.chunks(333).flat_map(|chunk| stage.process(chunk)).collect();
+        assert_eq!(streamed, whole);
+    }
+
+    #[test]
+    fn overview_stays_bounded_and_keeps_extremes() {
+        let mut builder = OverviewBuilder::default();
+        let mut samples = sine(50.0, 1000.0, 100_000);
+        samples[77_777] = -0.9;
+        for chunk in samples.chunks(1000) {
+            builder.push(chunk);
+        }
+        let pairs = builder.finish();
+        assert!((OVERVIEW_BUCKETS..=2 * OVERVIEW_BUCKETS).contains(&pairs.len()));
+        assert_eq!(pairs.iter().map(|&(min, _)| min).fold(0.0, f32::min), -0.9);
+    }
+
+    // Runs `frames` stereo frames of `value` through the sample conversion
+    fn convert<S: Sample>(
+        value: S,
+        frames: usize,
+        wrap: impl Fn(&AudioBuffer<S>) -> AudioBufferRef<'_>,
+    ) -> WaveformChunk {
+        let spec = SignalSpec::new(44100, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+        let mut buffer = AudioBuffer::<S>::new(frames as u64, spec);
+        buffer.render_reserved(Some(frames));
+        for channel in 0..2 {
+            buffer.chan_mut(channel).fill(value);
+        }
+        WaveformGenerator::process_audio_buffer(wrap(&buffer))
+    }
+
+    fn assert_silent(chunk: &WaveformChunk) {
+        for sample in chunk.mono.iter().chain(&chunk.left).chain(&chunk.right) {
+            assert!(sample.abs() < 1e-6, "{} is not silence", sample);
+        }
+    }
+
+    #[test]
+    fn unsigned_midpoints_are_silence() {
+        assert_silent(&convert(u8::MID, 4, |buffer| AudioBufferRef::U8(Cow::Borrowed(buffer))));
+        assert_silent(&convert(u16::MID, 4, |buffer| AudioBufferRef::U16(Cow::Borrowed(buffer))));
+        assert_silent(&convert(u24::MID, 4, |buffer| AudioBufferRef::U24(Cow::Borrowed(buffer))));
+        assert_silent(&convert(u32::MID, 4, |buffer| AudioBufferRef::U32(Cow::Borrowed(buffer))));
+    }
+
+    #[test]
+    fn over_unity_floats_are_clamped() {
+        let chunk = convert(1.5f32, 4, |buffer| AudioBufferRef::F32(Cow::Borrowed(buffer)));
+        assert!(chunk.mono.iter().chain(&chunk.left).chain(&chunk.right).all(|&sample| sample == 1.0));
+
+        let chunk = convert(-2.0f64, 4, |buffer| AudioBufferRef::F64(Cow::Borrowed(buffer)));
+        assert!(chunk.mono.iter().chain(&chunk.left).chain(&chunk.right).all(|&sample| sample == -1.0));
+    }
+
+    // Mono 16-bit WAV holding `frames` samples of `value`
+    fn write_wav(name: &str, value: i16, frames: usize) -> String {
+        let path = std::env::temp_dir().join(format!("rust_audio_player-{}-{}.wav", std::process::id(), name));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..frames {
+            writer.write_sample(value).unwrap();
+        }
+        writer.finalize().unwrap();
+        path.display().to_string()
+    }
+
+    fn wait_for(generator: &mut WaveformGenerator) {
+        let started = std::time::Instant::now();
+        while generator.is_generating() {
+            assert!(started.elapsed() < Duration::from_secs(10), "generation didn't finish");
+            generator.update_buffer();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn switching_tracks_never_mixes_buffers() {
+        let first = write_wav("switch-first", i16::MAX / 4, 200_000);
+        let second = write_wav("switch-second", i16::MIN / 2, 30_000);
+
+        let mut generator = WaveformGenerator::default();
+        for _ in 0..5 {
+            generator.generate_for(&first);
+            generator.update_buffer();
+            generator.generate_for(&second);
+        }
+        wait_for(&mut generator);
+
+        let buffer = generator.get_buffer();
+        assert_eq!(buffer.len(), 30_000);
+        assert!(buffer.iter().all(|&sample| (sample + 0.5).abs() < 0.001));
+
+        for file in [first, second] {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    // One Ogg page holding a single packet
+    fn ogg_page(serial: u32, sequence: u32, granule: u64, header_type: u8, packet: &[u8]) -> Vec<u8> {
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+
+        let mut page = b"OggS".to_vec();
+        page.push(0);
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(packet);
+
+        let crc = page.iter().fold(0u32, |mut crc, &byte| {
+            crc ^= (byte as u32) << 24;
+            for _ in 0..8 {
+                crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04C1_1DB7 } else { crc << 1 };
+            }
+            crc
+        });
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    // A complete Ogg FLAC stream of mono frames all at one value
+    fn ogg_flac_segment(serial: u32, rate: u32, value: i16, frames: usize) -> Vec<u8> {
+        let mut ident = vec![0x7F];
+        ident.extend_from_slice(b"FLAC");
+        ident.extend_from_slice(&[1, 0, 0, 0]);
+        ident.extend_from_slice(b"fLaC");
+        ident.extend_from_slice(&[0x80, 0, 0, 34]);
+        ident.extend_from_slice(&flac_writer::stream_info(1, rate, 16, frames as u64, (0, 0)));
+        let mut stream = ogg_page(serial, 0, 0, 0x02, &ident);
+
+        let samples = vec![value as i32; frames];
+        let blocks: Vec<&[i32]> = samples.chunks(4096).collect();
+        let mut granule = 0;
+        for (index, block) in blocks.iter().enumerate() {
+            granule += block.len() as u64;
+            let header_type = if index + 1 == blocks.len() { 0x04 } else { 0 };
+            let frame = flac_writer::encode_frame(block, 1, 16, index as u64);
+            stream.extend(ogg_page(serial, index as u32 + 1, granule, header_type, &frame));
+        }
+        stream
+    }
+
+    #[test]
+    fn chained_segments_at_different_rates_all_decode() {
+        let path = std::env::temp_dir().join(format!("rust_audio_player-{}-chained.ogg", std::process::id()));
+        let mut chained = ogg_flac_segment(1, 8000, i16::MAX / 4, 8000);
+        chained.extend(ogg_flac_segment(2, 16_000, i16::MIN / 2, 16_000));
+        fs::write(&path, chained).unwrap();
+
+        let mut generator = WaveformGenerator::default();
+        generator.generate_for(&path.display().to_string());
+        wait_for(&mut generator);
+
+        // Both seconds, the second resampled to the first segment's rate
+        let buffer = generator.get_buffer();
+        assert_eq!(generator.samples_per_second(), 8000.0);
+        assert!((15_900..=16_100).contains(&buffer.len()), "{} samples", buffer.len());
+        assert!(buffer[100..7900].iter().all(|&sample| (sample - 0.25).abs() < 0.001));
+        assert!(buffer[8100..15_900].iter().all(|&sample| (sample + 0.5).abs() < 0.001));
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Identifies the cache format; bumping the version makes older entries read as misses
+const MAGIC: &[u8; 4] = b"RAPW";
+const VERSION: u32 = 4;
+
+// Size the cache folder is trimmed back to, dropping the least recently used entries first
+const CACHE_LIMIT_BYTES: u64 = 32 * 1024 * 1024;
+// Larger files can't be entries this version wrote, so they're treated as misses unread
+const MAX_ENTRY_BYTES: u64 = 1024 * 1024;
+// Temporary files this old were left behind by a crash and are removed when trimming
+const STALE_TEMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Distinguishes temporary files of stores running at the same time
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+// Whole-track overview of a fully decoded file, saved so replaying it shows the complete
+// waveform at once. It holds a bounded number of peaks whatever the track's length.
+pub struct CachedWaveform {
+    pub duration: Option<Duration>,
+    pub genre: Option<String>,
+    // Length of audio the overview covers, in seconds
+    pub secs: f32,
+    // Largest absolute sample of the track
+    pub peak: f32,
+    // (min, max) of the mixed signal over equal slices of the track, in order
+    pub overview: Vec<(f32, f32)>,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    // Tests decode real files, so they get a folder of their own
+    if cfg!(test) {
+        return Some(std::env::temp_dir().join(format!("rust_audio_player-{}-waveforms", process::id())));
+    }
+    Some(dirs::cache_dir()?.join("rust_audio_player").join("waveforms"))
+}
+
+// One file per track under the user's cache folder, named after a hash of the track's path. The
+// hash is a fixed FNV-1a so names stay the same across builds.
+fn cache_path(file_path: &str) -> Option<PathBuf> {
+    let hash = file_path
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    Some(cache_dir()?.join(format!("{:016x}.bin", hash)))
+}
+
+// Modification time in nanoseconds and size; an entry is only used while both still match
+fn file_stamp(file_path: &str) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(file_path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok((modified.as_nanos() as u64, metadata.len()))
+}
+
+// The cached waveform of a file, or None when there is none, the file changed since or it was
+// generated with other parameters. `params` describes the generation settings the entry must
+// match. A hit marks the entry as recently used.
+pub fn load(file_path: &str, params: u64) -> Option<CachedWaveform> {
+    let path = cache_path(file_path)?;
+    let mut file = File::options().read(true).write(true).open(path).ok()?;
+    if file.metadata().ok()?.len() > MAX_ENTRY_BYTES {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    let _ = file.set_modified(SystemTime::now());
+    read_entry(&mut bytes.as_slice(), file_path, params).ok().flatten()
+}
+
+// Writes the waveform of a file to the cache, replacing any older entry, then trims the cache
+// folder. The entry is written to a temporary file first so a crash can't leave a truncated one
+// behind.
+pub fn store(file_path: &str, params: u64, waveform: &CachedWaveform) -> io::Result<()> {
+    let path = cache_path(file_path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache folder"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+    let temp_path = path.with_extension(format!("{}-{}.tmp", process::id(), temp_id));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    write_entry(&mut writer, file_path, params, waveform)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, &path)?;
+    trim_cache()
+}
+
+// Deletes every cached waveform
+pub fn clear() -> io::Result<()> {
+    let Some(dir) = cache_dir() else {
+        return Ok(());
+    };
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// Removes the least recently used entries until the folder is under CACHE_LIMIT_BYTES. Loading
+// an entry bumps its modification time, so that time orders entries by last use.
+fn trim_cache() -> io::Result<()> {
+    let Some(dir) = cache_dir() else {
+        return Ok(());
+    };
+
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        let age = metadata.modified().ok().and_then(|modified| modified.elapsed().ok());
+        let is_temp = path.extension().is_some_and(|extension| extension == "tmp");
+        if is_temp && age.is_some_and(|age| age > STALE_TEMP_AGE) {
+            let _ = fs::remove_file(&path);
+        }
+        if path.extension().is_none_or(|extension| extension != "bin") {
+            continue;
+        }
+        total += metadata.len();
+        entries.push((metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len(), path));
+    }
+
+    entries.sort_by_key(|(used, _, _)| *used);
+    for (_, size, path) in entries {
+        if total <= CACHE_LIMIT_BYTES {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= size;
+    }
+    Ok(())
+}
+
+fn write_entry(writer: &mut impl Write, file_path: &str, params: u64, waveform: &CachedWaveform) -> io::Result<()> {
+    let (modified, size) = file_stamp(file_path)?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&params.to_le_bytes())?;
+    write_bytes(writer, file_path.as_bytes())?;
+    writer.write_all(&modified.to_le_bytes())?;
+    writer.write_all(&size.to_le_bytes())?;
+    // Zero stands for an unknown duration
+    let duration_nanos = waveform.duration.map_or(0, |duration| duration.as_nanos() as u64);
+    writer.write_all(&duration_nanos.to_le_bytes())?;
+    write_bytes(writer, waveform.genre.as_deref().unwrap_or("").as_bytes())?;
+    writer.write_all(&waveform.secs.to_le_bytes())?;
+    writer.write_all(&waveform.peak.to_le_bytes())?;
+    let samples: Vec<f32> = waveform.overview.iter().flat_map(|&(min, max)| [min, max]).collect();
+    write_samples(writer, &samples)
+}
+
+// Ok(None) when the entry belongs to another path, an older version of the file or other
+// generation parameters. Reads from the whole entry in memory so every stored length can be
+// checked against what's left.
+fn read_entry(reader: &mut &[u8], file_path: &str, params: u64) -> io::Result<Option<CachedWaveform>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(reader)? != VERSION || read_u64(reader)? != params {
+        return Ok(None);
+    }
+    // Paths are stored in full since different paths can share a hash
+    if read_bytes(reader)? != file_path.as_bytes() {
+        return Ok(None);
+    }
+    if (read_u64(reader)?, read_u64(reader)?) != file_stamp(file_path)? {
+        return Ok(None);
+    }
+
+    let duration = Some(read_u64(reader)?).filter(|&nanos| nanos > 0).map(Duration::from_nanos);
+    let genre = String::from_utf8(read_bytes(reader)?).ok().filter(|genre| !genre.is_empty());
+    let secs = f32::from_bits(read_u32(reader)?);
+    let peak = f32::from_bits(read_u32(reader)?);
+    let overview = read_samples(reader)?.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect();
+
+    Ok(Some(CachedWaveform {
+        duration,
+        genre,
+        secs,
+        peak,
+        overview,
+    }))
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes(reader: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as usize;
+    Ok(take(reader, len)?.to_vec())
+}
+
+// The next `len` bytes, failing without allocating when a corrupt length runs past the end
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if len > reader.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Length runs past the end of the entry"));
+    }
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(bytes)
+}
+
+// Samples are stored as 16-bit integers, plenty for drawing and half the size of floats
+fn write_samples(writer: &mut impl Write, samples: &[f32]) -> io::Result<()> {
+    writer.write_all(&(samples.len() as u64).to_le_bytes())?;
+    for &sample in samples {
+        let quantized = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        writer.write_all(&quantized.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_samples(reader: &mut &[u8]) -> io::Result<Vec<f32>> {
+    let len = usize::try_from(read_u64(reader)?)
+        .ok()
+        .and_then(|len| len.checked_mul(2))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Sample count is out of range"))?;
+    let bytes = take(reader, len)?;
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]) as f32 / i16::MAX as f32)
+        .collect())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_round_trip() {
+        let mut bytes = Vec::new();
+        write_samples(&mut bytes, &[0.0, 0.5, -1.0, 2.0]).unwrap();
+        let samples = read_samples(&mut bytes.as_slice()).unwrap();
+        let expected = [0.0, 0.5, -1.0, 1.0];
+        assert!(samples.iter().zip(expected).all(|(sample, expected)| (sample - expected).abs() < 1e-4));
+    }
+
+    #[test]
+    fn lengths_past_the_end_are_rejected() {
+        let mut bytes = 1000u64.to_le_bytes().to_vec();
+        bytes.extend([0u8; 16]);
+        assert!(read_samples(&mut bytes.as_slice()).is_err());
+
+        let bytes = 1000u32.to_le_bytes();
+        assert!(read_bytes(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn overflowing_sample_count_is_rejected() {
+        let bytes = u64::MAX.to_le_bytes();
+        assert!(read_samples(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -1,22 +1,33 @@
 mod app;
 mod audio;
+mod settings;
+mod ui;
 mod utils;
 
 use eframe::egui::ViewportBuilder;
 use app::AudioPlayerApp;
 use eframe::Error;
 use eframe::NativeOptions;
+use settings::Settings;
+
+const APP_NAME: &str = "Audio Player";
 
 fn main() -> Result<(), Error> {
+    let settings = Settings::load_before_launch(APP_NAME);
+
     let options = NativeOptions {
         viewport: ViewportBuilder::default()
-            .with_inner_size([900.0, 400.0]),
+            .with_inner_size(settings.initial_window_size())
+            .with_maximized(settings.window_maximized),
+        vsync: settings.vsync,
+        // Window geometry is restored from our own settings instead
+        persist_window: false,
         ..Default::default()
     };
 
     eframe::run_native(
-        "Audio Player",
+        APP_NAME,
         options,
-        Box::new(|_cc| Ok(Box::<AudioPlayerApp>::default())),
+        Box::new(|cc| Ok(Box::new(AudioPlayerApp::new(cc)))),
     )
 }
\ No newline at end of file
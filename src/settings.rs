@@ -0,0 +1,194 @@
+use crate::audio::crossfade::CrossfadeCurve;
+use crate::audio::effects::CompressorPreset;
+use crate::audio::waveform::{EnvelopeMode, WaveformScale};
+use crate::utils::file_scanner::{ScanFilter, SortKey, DEFAULT_EXTENSIONS};
+use crate::ui::waveform_visualizer::{WaveformChannelMode, WaveformColorMode, WaveformStyle, WaveformView};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+// What a click on a file in the sidebar list does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClickAction {
+    PlayOnClick,
+    // Selecting first makes browsing a long list safe; double-click commits to playing
+    #[default]
+    DoubleClickToPlay,
+    // Single click plays a short snippet from the loudest part, double-click plays the track
+    PreviewOnClick,
+}
+
+impl ClickAction {
+    pub const ALL: [ClickAction; 3] = [Self::PlayOnClick, Self::DoubleClickToPlay, Self::PreviewOnClick];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PlayOnClick => "Play on click",
+            Self::DoubleClickToPlay => "Click selects, double-click plays",
+            Self::PreviewOnClick => "Click previews, double-click plays",
+        }
+    }
+}
+
+const DEFAULT_WINDOW_SIZE: [f32; 2] = [900.0, 400.0];
+const MIN_WINDOW_SIZE: [f32; 2] = [400.0, 300.0];
+const MAX_WINDOW_SIZE: [f32; 2] = [8192.0, 8192.0];
+
+// User preferences persisted between launches through eframe's storage
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Master volume as a linear factor
+    pub volume: f32,
+    pub night_mode: bool,
+    pub compressor_preset: CompressorPreset,
+    pub click_action: ClickAction,
+    // Seconds to keep the output device open after playback stops; 0 keeps it open
+    pub idle_release_secs: u32,
+    pub waveform_style: WaveformStyle,
+    pub waveform_view: WaveformView,
+    pub rectified_waveform: bool,
+    // Scale the waveform so the track's loudest peak reaches the full height
+    pub normalize_waveform: bool,
+    pub waveform_color_mode: WaveformColorMode,
+    // Draw stereo files as separate left and right waveforms; mono files always use the mixed one
+    pub waveform_channel_mode: WaveformChannelMode,
+    pub waveform_envelope: EnvelopeMode,
+    pub waveform_scale: WaveformScale,
+    // Memory ceiling for the waveform of one track, in megabytes
+    pub waveform_memory_mb: u32,
+    // Level below which audio counts as a silent gap for gap navigation, in dBFS
+    pub silence_threshold_db: f32,
+    // Audio batched per waveform update from the worker, in milliseconds
+    pub waveform_chunk_ms: u32,
+    // Apply each track's ReplayGain tag so tracks play at a similar loudness
+    pub replay_gain: bool,
+    // Manual gain offsets in dB keyed by file path
+    pub track_gains: HashMap<String, f32>,
+    // Overlap between tracks in seconds; 0 disables crossfading
+    pub crossfade_secs: f32,
+    pub crossfade_curve: CrossfadeCurve,
+    // Named positions within tracks keyed by file path, kept sorted by position
+    pub bookmarks: HashMap<String, Vec<(Duration, String)>>,
+    // UI frame rate while something is animating
+    pub target_fps: u32,
+    // Applied on the next launch, since the renderer is configured before the window opens
+    pub vsync: bool,
+    // Leave dotfiles, dot-folders and the folders below out of directory scans
+    pub skip_hidden_files: bool,
+    pub ignored_folders: Vec<String>,
+    // Order of the scanned file list
+    pub file_sort: SortKey,
+    // Walk into symlinked folders during scans
+    pub follow_symlinks: bool,
+    // File extensions scans and drops accept, lowercase and without the dot
+    pub audio_extensions: Vec<String>,
+    // Window size in points and maximized state, restored on the next launch. The position is
+    // deliberately not restored so the window can't reappear on a disconnected monitor.
+    pub window_size: [f32; 2],
+    pub window_maximized: bool,
+    // Append the next track ahead of time so albums play without gaps
+    pub gapless: bool,
+    // Fade applied when tracks start and stop, in milliseconds; 0 disables it
+    pub fade_ms: u32,
+    // Show elapsed and total time with milliseconds
+    pub precise_time_display: bool,
+    // Playback rate, where 1.0 is normal speed
+    pub playback_speed: f32,
+    // Where playback of each file was left, for resuming long tracks
+    pub saved_positions: HashMap<String, Duration>,
+    // Resume saved positions without asking
+    pub auto_resume_position: bool,
+    // Output device name; None follows the system default
+    pub output_device: Option<String>,
+    // Reopen the last track at its saved position on startup
+    pub resume_on_launch: bool,
+    // Session state captured on save. The directory is always reopened; the file and position
+    // only with resume on launch.
+    pub last_directory: Option<String>,
+    pub last_file: Option<String>,
+    pub last_position: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            night_mode: false,
+            compressor_preset: CompressorPreset::default(),
+            click_action: ClickAction::default(),
+            idle_release_secs: 30,
+            waveform_style: WaveformStyle::default(),
+            waveform_view: WaveformView::default(),
+            rectified_waveform: false,
+            normalize_waveform: false,
+            waveform_color_mode: WaveformColorMode::default(),
+            waveform_channel_mode: WaveformChannelMode::default(),
+            waveform_envelope: EnvelopeMode::default(),
+            waveform_scale: WaveformScale::default(),
+            waveform_memory_mb: 256,
+            waveform_chunk_ms: 50,
+            silence_threshold_db: -50.0,
+            replay_gain: false,
+            track_gains: HashMap::new(),
+            crossfade_secs: 0.0,
+            crossfade_curve: CrossfadeCurve::default(),
+            bookmarks: HashMap::new(),
+            target_fps: 30,
+            vsync: true,
+            skip_hidden_files: true,
+            ignored_folders: [".Trash", "$RECYCLE.BIN", "System Volume Information", "@eaDir"]
+                .map(String::from)
+                .to_vec(),
+            file_sort: SortKey::default(),
+            follow_symlinks: false,
+            audio_extensions: DEFAULT_EXTENSIONS.map(String::from).to_vec(),
+            window_size: DEFAULT_WINDOW_SIZE,
+            window_maximized: false,
+            gapless: true,
+            fade_ms: 150,
+            precise_time_display: false,
+            playback_speed: 1.0,
+            saved_positions: HashMap::new(),
+            auto_resume_position: false,
+            output_device: None,
+            resume_on_launch: false,
+            last_directory: None,
+            last_file: None,
+            last_position: Duration::ZERO,
+        }
+    }
+}
+
+impl Settings {
+    // The saved window size, clamped so a corrupt or stale value can't open an unusable window
+    pub fn initial_window_size(&self) -> [f32; 2] {
+        let [width, height] = self.window_size;
+        if !width.is_finite() || !height.is_finite() {
+            return DEFAULT_WINDOW_SIZE;
+        }
+        [
+            width.clamp(MIN_WINDOW_SIZE[0], MAX_WINDOW_SIZE[0]),
+            height.clamp(MIN_WINDOW_SIZE[1], MAX_WINDOW_SIZE[1]),
+        ]
+    }
+
+    pub fn scan_filter(&self) -> ScanFilter {
+        ScanFilter {
+            skip_hidden: self.skip_hidden_files,
+            ignored_folders: self.ignored_folders.clone(),
+            extensions: self.audio_extensions.clone(),
+            follow_symlinks: self.follow_symlinks,
+        }
+    }
+
+    // Reads the settings eframe persisted for `app_id`, for options needed before the window exists
+    pub fn load_before_launch(app_id: &str) -> Self {
+        eframe::storage_dir(app_id)
+            .and_then(|dir| fs::read_to_string(dir.join("app.ron")).ok())
+            .and_then(|contents| ron::from_str::<HashMap<String, String>>(&contents).ok())
+            .and_then(|values| ron::from_str(values.get(eframe::APP_KEY)?).ok())
+            .unwrap_or_default()
+    }
+}
\ No newline at end of file
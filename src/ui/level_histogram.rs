@@ -0,0 +1,66 @@
+use eframe::egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Stroke};
+
+// Paints a level histogram as vertical bars, loudest bin on the right
+pub struct LevelHistogram<'a> {
+    bins: &'a [u32],
+    // Width of each bin in dB, used for the axis labels
+    bin_db: f32,
+    color: Color32,
+}
+
+impl<'a> LevelHistogram<'a> {
+    // `bins` are ordered from the loudest level down, as produced by the waveform generator
+    pub fn new(bins: &'a [u32], bin_db: f32) -> Self {
+        Self {
+            bins,
+            bin_db,
+            color: Color32::LIGHT_BLUE,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color32) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn paint(&self, painter: &Painter, rect: Rect) {
+        let max = self.bins.iter().copied().max().unwrap_or(0);
+        if max == 0 || rect.width() <= 0.0 {
+            return;
+        }
+
+        let label_font = FontId::proportional(10.0);
+        let label_height = 14.0;
+        let plot = Rect::from_min_max(rect.min, Pos2::new(rect.right(), rect.bottom() - label_height));
+        let bar_width = plot.width() / self.bins.len() as f32;
+
+        for (i, &count) in self.bins.iter().rev().enumerate() {
+            let height = plot.height() * count as f32 / max as f32;
+            let left = plot.left() + i as f32 * bar_width;
+            let bar = Rect::from_min_max(
+                Pos2::new(left + 1.0, plot.bottom() - height),
+                Pos2::new(left + bar_width - 1.0, plot.bottom()),
+            );
+            painter.rect_filled(bar, 0.0, self.color);
+        }
+
+        painter.line_segment(
+            [plot.left_bottom(), plot.right_bottom()],
+            Stroke::new(1.0, Color32::GRAY),
+        );
+
+        // Label every fourth bin edge with its level in dBFS
+        let bins = self.bins.len();
+        for edge in (0..=bins).step_by(4) {
+            let db = -((bins - edge) as f32) * self.bin_db;
+            let x = plot.left() + edge as f32 * bar_width;
+            painter.text(
+                Pos2::new(x, rect.bottom()),
+                Align2::CENTER_BOTTOM,
+                format!("{:.0}", db),
+                label_font.clone(),
+                Color32::GRAY,
+            );
+        }
+    }
+}
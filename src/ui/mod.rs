@@ -0,0 +1,3 @@
+pub(crate) mod level_histogram;
+pub(crate) mod spectrogram_view;
+pub(crate) mod waveform_visualizer;
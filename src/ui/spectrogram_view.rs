@@ -0,0 +1,28 @@
+use crate::audio::spectrum::BINS;
+use eframe::egui::{Color32, ColorImage};
+
+// Color stops from silence to full scale: black, deep blue, magenta, orange, pale yellow
+const PALETTE: [(u8, u8, u8); 5] = [(0, 0, 0), (30, 20, 110), (160, 30, 130), (245, 120, 30), (255, 240, 170)];
+
+// Maps a 0..=255 level onto the palette
+fn level_color(level: u8) -> Color32 {
+    let position = level as f32 / 255.0 * (PALETTE.len() - 1) as f32;
+    let index = (position as usize).min(PALETTE.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (PALETTE[index], PALETTE[index + 1]);
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Color32::from_rgb(lerp(from.0, to.0), lerp(from.1, to.1), lerp(from.2, to.2))
+}
+
+// Renders spectrogram frames as an image with time running left to right and low frequencies
+// at the bottom
+pub fn spectrogram_image(frames: &[[u8; BINS]]) -> ColorImage {
+    let width = frames.len().max(1);
+    let mut image = ColorImage::new([width, BINS], Color32::BLACK);
+    for (x, frame) in frames.iter().enumerate() {
+        for (bin, &level) in frame.iter().enumerate() {
+            image.pixels[(BINS - 1 - bin) * width + x] = level_color(level);
+        }
+    }
+    image
+}
@@ -0,0 +1,252 @@
+use eframe::egui::ecolor::Hsva;
+use eframe::egui::{Color32, Mesh, Painter, Pos2, Rect, Shape, Stroke};
+use serde::{Deserialize, Serialize};
+
+// How the waveform samples are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveformStyle {
+    #[default]
+    Line,
+    Filled,
+}
+
+impl WaveformStyle {
+    pub const ALL: [WaveformStyle; 2] = [Self::Line, Self::Filled];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Line => "Line",
+            Self::Filled => "Filled",
+        }
+    }
+}
+
+// Where the waveform color comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveformColorMode {
+    #[default]
+    Fixed,
+    // Quiet tracks are cool blue, loud tracks warm red
+    Loudness,
+    // Each genre tag maps to a stable hue
+    Genre,
+}
+
+impl WaveformColorMode {
+    pub const ALL: [WaveformColorMode; 3] = [Self::Fixed, Self::Loudness, Self::Genre];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Fixed => "Fixed",
+            Self::Loudness => "By loudness",
+            Self::Genre => "By genre",
+        }
+    }
+}
+
+// Whether stereo files are drawn as one mixed waveform or as left over right
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveformChannelMode {
+    #[default]
+    Mono,
+    Stereo,
+}
+
+impl WaveformChannelMode {
+    pub const ALL: [WaveformChannelMode; 2] = [Self::Mono, Self::Stereo];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Mono => "Mixed",
+            Self::Stereo => "Left / right",
+        }
+    }
+}
+
+// What the large scrolling view shows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveformView {
+    #[default]
+    Waveform,
+    // Frequency content over time
+    Spectrogram,
+}
+
+// Levels mapped to the ends of the loudness color scale, in dBFS
+const QUIET_DB: f32 = -30.0;
+const LOUD_DB: f32 = -8.0;
+
+// Blue for quiet tracks through to red for loud ones
+pub fn loudness_color(loudness_db: f32) -> Color32 {
+    let t = ((loudness_db - QUIET_DB) / (LOUD_DB - QUIET_DB)).clamp(0.0, 1.0);
+    let hue = (1.0 - t) * (220.0 / 360.0);
+    Hsva::new(hue, 0.6, 1.0, 1.0).into()
+}
+
+// A hue derived from the genre name, so the same genre always gets the same color
+pub fn genre_color(genre: &str) -> Color32 {
+    let hash = genre
+        .trim()
+        .to_lowercase()
+        .bytes()
+        .fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    let hue = (hash % 360) as f32 / 360.0;
+    Hsva::new(hue, 0.55, 1.0, 1.0).into()
+}
+
+// Paints (min, max) peak pairs as a compact full-track strip, one bar per pair
+pub struct WaveformOverview<'a> {
+    peaks: &'a [(f32, f32)],
+    color: Color32,
+}
+
+impl<'a> WaveformOverview<'a> {
+    pub fn new(peaks: &'a [(f32, f32)]) -> Self {
+        Self {
+            peaks,
+            color: Color32::LIGHT_BLUE,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color32) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn paint(&self, painter: &Painter, rect: Rect) {
+        if self.peaks.is_empty() || rect.width() <= 0.0 {
+            return;
+        }
+
+        let bar_width = rect.width() / self.peaks.len() as f32;
+        let half_height = rect.height() / 2.0;
+
+        let mut mesh = Mesh::default();
+        mesh.reserve_triangles(self.peaks.len() * 2);
+        mesh.reserve_vertices(self.peaks.len() * 4);
+
+        for (i, &(min, max)) in self.peaks.iter().enumerate() {
+            let x = rect.left() + i as f32 * bar_width;
+            let top = rect.center().y - max.clamp(-1.0, 1.0) * half_height;
+            let bottom = (rect.center().y - min.clamp(-1.0, 1.0) * half_height).max(top + 1.0);
+            mesh.add_colored_rect(Rect::from_min_max(Pos2::new(x, top), Pos2::new(x + bar_width, bottom)), self.color);
+        }
+
+        painter.add(Shape::mesh(mesh));
+    }
+}
+
+// Paints a slice of waveform samples into a rect
+pub struct WaveformVisualizer<'a> {
+    samples: &'a [f32],
+    color: Color32,
+    style: WaveformStyle,
+    // Draw |sample| rising from the bottom edge instead of a symmetric wave around the center
+    rectified: bool,
+}
+
+impl<'a> WaveformVisualizer<'a> {
+    pub fn new(samples: &'a [f32]) -> Self {
+        Self {
+            samples,
+            color: Color32::LIGHT_BLUE,
+            style: WaveformStyle::default(),
+            rectified: false,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color32) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_style(mut self, style: WaveformStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_rectified(mut self, rectified: bool) -> Self {
+        self.rectified = rectified;
+        self
+    }
+
+    // Baseline y and the pixel height of a full-scale sample
+    fn baseline(&self, rect: Rect) -> (f32, f32) {
+        if self.rectified {
+            (rect.bottom(), rect.height())
+        } else {
+            (rect.center().y, rect.height() / 2.0)
+        }
+    }
+
+    fn level(&self, sample: f32) -> f32 {
+        if self.rectified { sample.abs() } else { sample }
+    }
+
+    pub fn paint(&self, painter: &Painter, rect: Rect) {
+        if self.samples.is_empty() || rect.width() <= 0.0 {
+            return;
+        }
+
+        match self.style {
+            WaveformStyle::Line => self.paint_line(painter, rect),
+            WaveformStyle::Filled => self.paint_filled(painter, rect),
+        }
+    }
+
+    fn paint_line(&self, painter: &Painter, rect: Rect) {
+        let (baseline_y, wave_height) = self.baseline(rect);
+        let wave_width = rect.width() / self.samples.len() as f32;
+
+        let points: Vec<Pos2> = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let x = rect.left() + (i as f32 * wave_width);
+                let y = baseline_y - (self.level(*sample) * wave_height);
+                Pos2 { x, y }
+            })
+            .collect();
+
+        painter.add(Shape::line(points, Stroke::new(1.5, self.color)));
+    }
+
+    // Builds one triangle mesh with a min/max bar per pixel column, far cheaper than one shape per bar
+    fn paint_filled(&self, painter: &Painter, rect: Rect) {
+        let (baseline_y, wave_height) = self.baseline(rect);
+        let columns = (rect.width().ceil() as usize).min(self.samples.len()).max(1);
+        let column_width = rect.width() / columns as f32;
+        let samples_per_column = self.samples.len() as f32 / columns as f32;
+
+        let mut mesh = Mesh::default();
+        mesh.reserve_triangles(columns * 2);
+        mesh.reserve_vertices(columns * 4);
+
+        for column in 0..columns {
+            let start = (column as f32 * samples_per_column) as usize;
+            let end = (((column + 1) as f32 * samples_per_column) as usize).clamp(start + 1, self.samples.len());
+
+            let (min, max) = self.samples[start..end]
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), &s| {
+                    let level = self.level(s);
+                    (min.min(level), max.max(level))
+                });
+            // A rectified column always reaches down to the baseline
+            let min = if self.rectified { 0.0 } else { min };
+
+            let x = rect.left() + column as f32 * column_width;
+            let top = baseline_y - max * wave_height;
+            // Keep silent stretches visible as a hairline
+            let bottom = (baseline_y - min * wave_height).max(top + 1.0);
+
+            mesh.add_colored_rect(
+                Rect::from_min_max(Pos2::new(x, top), Pos2::new(x + column_width, bottom)),
+                self.color,
+            );
+        }
+
+        painter.add(Shape::mesh(mesh));
+    }
+}
\ No newline at end of file
@@ -1,30 +1,314 @@
-use walkdir::WalkDir;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::{DirEntry, WalkDir};
+
+// Extensions recognised as playable audio unless the user configures others, lowercase and
+// without the dot
+pub const DEFAULT_EXTENSIONS: [&str; 5] = ["mp3", "wav", "flac", "m4a", "ogg"];
+
+// Which entries a scan leaves out, and how it walks the tree
+#[derive(Clone, Debug)]
+pub struct ScanFilter {
+    // Skip files and folders whose name starts with a dot
+    pub skip_hidden: bool,
+    // Folder names (matched case-insensitively) that are not descended into
+    pub ignored_folders: Vec<String>,
+    // File extensions that are listed, lowercase and without the dot
+    pub extensions: Vec<String>,
+    // Descend into symlinked folders and list symlinked files
+    pub follow_symlinks: bool,
+}
+
+impl Default for ScanFilter {
+    fn default() -> Self {
+        Self {
+            skip_hidden: false,
+            ignored_folders: Vec::new(),
+            extensions: DEFAULT_EXTENSIONS.map(String::from).to_vec(),
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl ScanFilter {
+    fn excludes(&self, entry: &DirEntry) -> bool {
+        let name = entry.file_name().to_string_lossy();
+        if self.skip_hidden && name.starts_with('.') {
+            return true;
+        }
+        entry.file_type().is_dir() && self.ignored_folders.iter().any(|folder| folder.eq_ignore_ascii_case(&name))
+    }
+}
+
+// Order of the scanned file list
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    #[default]
+    Name,
+    // Most recently modified first, so new downloads show up at the top
+    Modified,
+    // Largest first
+    Size,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 3] = [Self::Name, Self::Modified, Self::Size];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Name => "Name",
+            Self::Modified => "Newest first",
+            Self::Size => "Largest first",
+        }
+    }
+
+    // Sorts paths in place. Metadata is read once per file; files that can't be read sort last,
+    // and ties fall back to the path so the order is stable between scans.
+    pub fn sort(self, files: &mut [String]) {
+        match self {
+            Self::Name => files.sort_by(|a, b| natural_cmp(a, b)),
+            Self::Modified => files.sort_by_cached_key(|file| {
+                let modified = fs::metadata(file).and_then(|metadata| metadata.modified()).ok();
+                (Reverse(modified), file.clone())
+            }),
+            Self::Size => files.sort_by_cached_key(|file| {
+                let size = fs::metadata(file).map(|metadata| metadata.len()).ok();
+                (Reverse(size), file.clone())
+            }),
+        }
+    }
+}
+
+// Compares strings so runs of digits order by value, putting `Track 2` before `Track 10`. Text
+// runs compare case-insensitively; exact ties fall back to a plain comparison.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    while let (Some(&a_char), Some(&b_char)) = (a_chars.peek(), b_chars.peek()) {
+        let ordering = if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+            let a_run = take_digits(&mut a_chars);
+            let b_run = take_digits(&mut b_chars);
+            // Leading zeros don't change the value, and a longer run of significant digits is larger
+            let a_value = a_run.trim_start_matches('0');
+            let b_value = b_run.trim_start_matches('0');
+            a_value.len().cmp(&b_value.len()).then_with(|| a_value.cmp(b_value))
+        } else {
+            a_chars.next();
+            b_chars.next();
+            a_char.to_lowercase().cmp(b_char.to_lowercase())
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a_chars.count().cmp(&b_chars.count()).then_with(|| a.cmp(b))
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut run = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        run.push(digit);
+    }
+    run
+}
 
 pub struct AudioFileScanner;
 
 impl AudioFileScanner {
-    pub fn scan_directory(dir_path: &str, max_depth: usize) -> Vec<String> {
-        let mut audio_files = Vec::new();
+    // Lazily walks the directory, yielding audio files in the order they're found. When following
+    // symlinks, a link back into one of its own ancestors is reported by WalkDir as a loop error
+    // and skipped like any other unreadable entry.
+    pub fn scan_directory<'a>(
+        dir_path: &str,
+        max_depth: usize,
+        filter: &'a ScanFilter,
+    ) -> impl Iterator<Item = String> + 'a {
+        Self::walk(dir_path, max_depth, filter)
+            .filter(|entry| entry.file_type().is_file() && Self::is_supported(entry.path(), &filter.extensions))
+            .map(|entry| entry.path().display().to_string())
+    }
 
-        for entry in WalkDir::new(dir_path)
+    fn walk<'a>(dir_path: &str, max_depth: usize, filter: &'a ScanFilter) -> impl Iterator<Item = DirEntry> + 'a {
+        WalkDir::new(dir_path)
             .min_depth(1)
             .max_depth(max_depth)
+            .follow_links(filter.follow_symlinks)
             .into_iter()
+            .filter_entry(|e| !filter.excludes(e))
             .filter_map(|e| e.ok())
-        {
-            if entry.file_type().is_file() {
-                let path = entry.path().display().to_string();
-                if Self::is_audio_file(&path) {
-                    audio_files.push(path);
+    }
+
+    // Scans on a worker thread so slow or network-mounted folders don't block the UI.
+    // The receiver yields each file as it is found and disconnects once the walk completes.
+    // Dropping the receiver stops the walk.
+    pub fn scan_in_background(dir_path: &str, max_depth: usize, filter: ScanFilter) -> Receiver<String> {
+        let (tx, rx) = channel();
+        let dir_path = dir_path.to_string();
+
+        thread::spawn(move || {
+            for file in Self::scan_directory(&dir_path, max_depth, &filter) {
+                if tx.send(file).is_err() {
+                    break;
                 }
             }
+        });
+
+        rx
+    }
+
+    // Whether a path has one of the accepted audio extensions. Shared by everything that accepts
+    // paths from outside the scanner so they all agree on what can be played.
+    pub fn is_supported(path: &Path, extensions: &[String]) -> bool {
+        // Extensions are compared case-insensitively so files like `song.MP3` are found too
+        let Some(extension) = path.extension() else {
+            return false;
+        };
+        let extension = extension.to_string_lossy().to_lowercase();
+        extensions.contains(&extension)
+    }
+}
+
+// How often a watched folder tree is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+// Changes must stop for this long before they're reported, so a large copy causes one rescan
+const WATCH_SETTLE: Duration = Duration::from_secs(3);
+
+// Watches the folders a scan would visit and reports when files are added, removed or renamed.
+// It polls folder modification times, which are cheap to read and change on every platform
+// whenever an entry inside the folder does. Dropping the watcher stops its thread.
+//
+// Polling is used instead of OS change notifications (the `notify` crate) because those miss
+// changes on network shares and some FUSE mounts, where music libraries often live, and inotify
+// needs a watch per folder that large trees can exhaust. Only folders are stat'ed, not files, so
+// a pass over a library of a few thousand albums stays in the low milliseconds.
+pub struct DirectoryWatcher {
+    receiver: Receiver<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl DirectoryWatcher {
+    pub fn watch(dir_path: &str, max_depth: usize, filter: ScanFilter) -> Self {
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let dir_path = dir_path.to_string();
+
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut last = Self::snapshot(&dir_path, max_depth, &filter);
+            let mut changed_at: Option<Instant> = None;
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                if stopped.load(AtomicOrdering::Relaxed) {
+                    break;
+                }
+
+                let current = Self::snapshot(&dir_path, max_depth, &filter);
+                if current != last {
+                    last = current;
+                    changed_at = Some(Instant::now());
+                } else if changed_at.is_some_and(|at| at.elapsed() >= WATCH_SETTLE) {
+                    changed_at = None;
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self { receiver: rx, stop }
+    }
+
+    // Whether the folder changed since the last call
+    pub fn poll_changed(&self) -> bool {
+        let mut changed = false;
+        while self.receiver.try_recv().is_ok() {
+            changed = true;
+        }
+        changed
+    }
+
+    // Every watched folder with its modification time, in walk order
+    fn snapshot(dir_path: &str, max_depth: usize, filter: &ScanFilter) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let root = fs::metadata(dir_path).and_then(|metadata| metadata.modified()).ok();
+        let folders = AudioFileScanner::walk(dir_path, max_depth, filter)
+            .filter(|entry| entry.file_type().is_dir())
+            .map(|entry| {
+                let modified = entry.metadata().ok().and_then(|metadata| metadata.modified().ok());
+                (entry.into_path(), modified)
+            });
+        std::iter::once((PathBuf::from(dir_path), root)).chain(folders).collect()
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Empty folder under the system temp folder, unique to this test run
+    fn temp_folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_audio_player-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scanned_names(dir: &Path, filter: &ScanFilter) -> Vec<String> {
+        let mut names: Vec<String> = AudioFileScanner::scan_directory(&dir.display().to_string(), 3, filter)
+            .map(|file| Path::new(&file).file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn hidden_and_apple_double_files_are_skipped() {
+        let dir = temp_folder("hidden");
+        for name in ["song.mp3", "._song.mp3", ".hidden.mp3"] {
+            fs::write(dir.join(name), b"").unwrap();
         }
 
-        audio_files
+        let filter = ScanFilter {
+            skip_hidden: true,
+            ..ScanFilter::default()
+        };
+        assert_eq!(scanned_names(&dir, &filter), ["song.mp3"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn supported(name: &str) -> bool {
+        let extensions = DEFAULT_EXTENSIONS.map(String::from).to_vec();
+        AudioFileScanner::is_supported(Path::new(name), &extensions)
     }
 
-    fn is_audio_file(path: &str) -> bool {
-        let extensions = [".mp3", ".wav", ".flac", ".m4a", ".ogg"];
-        extensions.iter().any(|ext| path.ends_with(ext))
+    #[test]
+    fn only_the_real_extension_counts() {
+        assert!(!supported("archive.mp3.txt"));
+        // A dotfile named after an extension has no extension at all
+        assert!(!supported(".flac"));
+        assert!(supported("song.FLAC"));
+    }
+
+    #[test]
+    fn extensions_match_in_any_case() {
+        for name in ["song.MP3", "track.Flac", "take.WaV", "clip.m4A"] {
+            assert!(supported(name), "{} should be supported", name);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,264 @@
+use image::{imageops, ImageFormat, RgbaImage};
+use jpeg_decoder::PixelFormat;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::codecs::CodecParameters;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Visual};
+use symphonia::core::probe::Hint;
+use symphonia::default::get_probe;
+
+// Largest edge of decoded cover art; bigger images are scaled down since they're shown small
+const MAX_COVER_SIZE: u32 = 256;
+
+// Decoded cover art as 8-bit RGBA pixels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverArt {
+    pub size: [usize; 2],
+    pub rgba: Vec<u8>,
+}
+
+// Descriptive tags of a track; any of them may be missing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<String>,
+    // Gain in dB that brings the track to the ReplayGain reference loudness
+    pub replay_gain_db: Option<f32>,
+    // Embedded picture, preferring the front cover when there are several
+    pub cover: Option<CoverArt>,
+}
+
+impl TrackMetadata {
+    // The tagged title, or the file name when the file has none
+    pub fn display_title(&self, path: &Path) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string())
+        })
+    }
+
+    // Fills in fields this revision has that are still missing. The album artist is only a
+    // fallback for the artist, so it's collected separately for the caller to apply once every
+    // revision has been read.
+    fn merge(&mut self, revision: &MetadataRevision, album_artist: &mut Option<String>) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string();
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            if tag.std_key == Some(StandardTagKey::ReplayGainTrackGain) {
+                // Stored as text such as "-6.48 dB"
+                let gain = value.to_lowercase().trim_end_matches("db").trim().parse().ok();
+                self.replay_gain_db = self.replay_gain_db.or(gain);
+                continue;
+            }
+
+            let (field, value) = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => (&mut self.title, value),
+                Some(StandardTagKey::Artist) => (&mut self.artist, value),
+                Some(StandardTagKey::AlbumArtist) => (&mut *album_artist, value),
+                Some(StandardTagKey::Album) => (&mut self.album, value),
+                // Dates are often full ISO dates; only the year is shown
+                Some(StandardTagKey::Date) | Some(StandardTagKey::ReleaseDate) => {
+                    (&mut self.year, value.split('-').next().unwrap_or(value))
+                }
+                _ => continue,
+            };
+            if field.is_none() {
+                *field = Some(value.to_string());
+            }
+        }
+
+        if self.cover.is_none() {
+            let visuals = revision.visuals();
+            let front = visuals.iter().find(|visual| visual.usage == Some(StandardVisualKey::FrontCover));
+            self.cover = front.into_iter().chain(visuals).find_map(decode_visual);
+        }
+    }
+}
+
+// Decodes an embedded JPEG or PNG picture; other formats are skipped
+fn decode_visual(visual: &Visual) -> Option<CoverArt> {
+    let data = &visual.data[..];
+    let image = if data.starts_with(&[0xFF, 0xD8]) {
+        let mut decoder = jpeg_decoder::Decoder::new(data);
+        let pixels = decoder.decode().ok()?;
+        let info = decoder.info()?;
+        let rgba: Vec<u8> = match info.pixel_format {
+            PixelFormat::RGB24 => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            PixelFormat::L8 => pixels.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            PixelFormat::L16 | PixelFormat::CMYK32 => return None,
+        };
+        RgbaImage::from_raw(info.width as u32, info.height as u32, rgba)?
+    } else {
+        image::load_from_memory_with_format(data, ImageFormat::Png).ok()?.to_rgba8()
+    };
+
+    let image = if image.width().max(image.height()) > MAX_COVER_SIZE {
+        let scale = MAX_COVER_SIZE as f32 / image.width().max(image.height()) as f32;
+        let width = ((image.width() as f32 * scale) as u32).max(1);
+        let height = ((image.height() as f32 * scale) as u32).max(1);
+        imageops::thumbnail(&image, width, height)
+    } else {
+        image
+    };
+
+    Some(CoverArt {
+        size: [image.width() as usize, image.height() as usize],
+        rgba: image.into_raw(),
+    })
+}
+
+// Reads title, artist, album, year, ReplayGain and cover art. Tags can live in the container (Vorbis comments, MP4
+// atoms) or ahead of it (an ID3 block in front of an MP3 stream), so both places are checked.
+// Unreadable files yield empty metadata.
+pub fn read_metadata(path: &Path) -> TrackMetadata {
+    let mut metadata = TrackMetadata::default();
+
+    let Ok(file) = File::open(path) else {
+        return metadata;
+    };
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension() {
+        hint.with_extension(&extension.to_string_lossy());
+    }
+
+    let Ok(mut probed) = get_probe().format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+    else {
+        return metadata;
+    };
+
+    let mut album_artist = None;
+    if let Some(revision) = probed.format.metadata().current() {
+        metadata.merge(revision, &mut album_artist);
+    }
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        metadata.merge(revision, &mut album_artist);
+    }
+    metadata.artist = metadata.artist.or(album_artist);
+
+    metadata
+}
+
+// Length declared by the container. Some containers leave the sample rate to the decoder but
+// still give a frame time base.
+pub fn codec_duration(params: &CodecParameters) -> Option<Duration> {
+    let n_frames = params.n_frames?;
+    if let Some(sample_rate) = params.sample_rate {
+        return Some(Duration::from_secs_f64(n_frames as f64 / sample_rate as f64));
+    }
+    let time = params.time_base?.calc_time(n_frames);
+    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+}
+
+// Reads a file's length from its container without decoding any audio. Fails when the file
+// can't be opened or probed, or doesn't declare its length.
+pub fn probe_duration(path: &Path) -> io::Result<Duration> {
+    let file = File::open(path)?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension() {
+        hint.with_extension(&extension.to_string_lossy());
+    }
+
+    let probed = get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let track = probed
+        .format
+        .default_track()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "File has no audio track"))?;
+
+    codec_duration(&track.codec_params)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Length is not declared"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use symphonia::core::meta::{MetadataBuilder, Tag, Value};
+    use symphonia::core::units::TimeBase;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust_audio_player-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn length_is_unknown_without_a_frame_count() {
+        let params = CodecParameters::new().with_sample_rate(44_100).with_time_base(TimeBase::new(1, 44_100)).clone();
+        assert_eq!(codec_duration(&params), None);
+    }
+
+    #[test]
+    fn frame_time_base_stands_in_for_a_missing_sample_rate() {
+        let params = CodecParameters::new().with_n_frames(90_500).with_time_base(TimeBase::new(1, 1000)).clone();
+        assert_eq!(codec_duration(&params), Some(Duration::from_millis(90_500)));
+    }
+
+    #[test]
+    fn probed_length_matches_the_written_wav() {
+        let path = temp_file("probe.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        // 2.5 seconds of stereo frames
+        for _ in 0..2 * 20_000 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let duration = probe_duration(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(duration.unwrap(), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn probing_a_non_audio_file_fails() {
+        let path = temp_file("notes.mp3");
+        fs::write(&path, "not audio at all").unwrap();
+
+        let duration = probe_duration(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(duration.is_err());
+        // So does one that's no longer there
+        assert!(probe_duration(&path).is_err());
+    }
+
+    fn revision(tags: &[(StandardTagKey, &str)]) -> MetadataRevision {
+        let mut builder = MetadataBuilder::new();
+        for &(key, value) in tags {
+            builder.add_tag(Tag::new(Some(key), "", Value::from(value)));
+        }
+        builder.metadata()
+    }
+
+    #[test]
+    fn album_artist_never_hides_the_track_artist() {
+        let mut metadata = TrackMetadata::default();
+        let mut album_artist = None;
+        metadata.merge(
+            &revision(&[(StandardTagKey::AlbumArtist, "Various Artists"), (StandardTagKey::Artist, "Nina Simone")]),
+            &mut album_artist,
+        );
+        assert_eq!(metadata.artist.as_deref(), Some("Nina Simone"));
+        assert_eq!(album_artist.as_deref(), Some("Various Artists"));
+    }
+}
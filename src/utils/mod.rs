@@ -1 +1,5 @@
-pub(crate) mod file_scanner;
\ No newline at end of file
+pub(crate) mod file_scanner;
+pub(crate) mod metadata;
+pub(crate) mod playlist;
+pub(crate) mod time_format;
+pub(crate) mod trash;
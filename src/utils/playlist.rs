@@ -0,0 +1,172 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Where a playlist entry points
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaylistLocation {
+    File(PathBuf),
+    Url(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct PlaylistEntry {
+    pub location: PlaylistLocation,
+    // Display title from #EXTINF or TitleN=, when the playlist provides one
+    pub title: Option<String>,
+}
+
+// Loads an M3U/M3U8 or PLS playlist. The format is detected from the content, falling back to
+// the extension, and relative paths are resolved against the playlist's folder.
+pub fn load_playlist(path: &Path) -> io::Result<Vec<PlaylistEntry>> {
+    let bytes = fs::read(path)?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let contents = contents.trim_start_matches('\u{feff}');
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    let first_line = contents.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+    let is_pls = first_line.eq_ignore_ascii_case("[playlist]")
+        || path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+
+    Ok(if is_pls {
+        parse_pls(contents, base_dir)
+    } else {
+        parse_m3u(contents, base_dir)
+    })
+}
+
+// Handles plain and extended M3U; the title comes from the #EXTINF line preceding an entry
+fn parse_m3u(contents: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending_title = None;
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds>,<title>
+            pending_title = info.split_once(',').map(|(_, title)| title.trim().to_string());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        entries.push(PlaylistEntry {
+            location: resolve_location(line, base_dir),
+            title: pending_title.take().filter(|title| !title.is_empty()),
+        });
+    }
+
+    entries
+}
+
+// Handles INI-style PLS: FileN= and TitleN= keys, ordered by N
+fn parse_pls(contents: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    let mut files: Vec<(u32, String)> = Vec::new();
+    let mut titles: Vec<(u32, String)> = Vec::new();
+
+    for line in contents.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim().to_string();
+
+        if let Some(index) = key.strip_prefix("file").and_then(|n| n.parse().ok()) {
+            files.push((index, value));
+        } else if let Some(index) = key.strip_prefix("title").and_then(|n| n.parse().ok()) {
+            titles.push((index, value));
+        }
+    }
+
+    files.sort_by_key(|(index, _)| *index);
+    files
+        .into_iter()
+        .filter(|(_, file)| !file.is_empty())
+        .map(|(index, file)| PlaylistEntry {
+            location: resolve_location(&file, base_dir),
+            title: titles
+                .iter()
+                .find(|(title_index, _)| *title_index == index)
+                .map(|(_, title)| title.clone())
+                .filter(|title| !title.is_empty()),
+        })
+        .collect()
+}
+
+fn resolve_location(entry: &str, base_dir: &Path) -> PlaylistLocation {
+    if let Some(path) = entry.strip_prefix("file://") {
+        return PlaylistLocation::File(PathBuf::from(percent_decode(path)));
+    }
+    if entry.contains("://") {
+        return PlaylistLocation::Url(entry.to_string());
+    }
+
+    let path = PathBuf::from(entry);
+    if path.is_absolute() {
+        PlaylistLocation::File(path)
+    } else {
+        PlaylistLocation::File(base_dir.join(path))
+    }
+}
+
+// Turns %XX escapes in a URI path back into the bytes they stand for. Malformed escapes are
+// kept as written.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(entry: &str) -> PlaylistLocation {
+        resolve_location(entry, Path::new("/playlists"))
+    }
+
+    #[test]
+    fn file_uris_are_percent_decoded() {
+        assert_eq!(
+            resolved("file:///music/My%20Song%20%231.mp3"),
+            PlaylistLocation::File(PathBuf::from("/music/My Song #1.mp3"))
+        );
+        assert_eq!(
+            resolved("file:///music/Caf%C3%A9%2fB.flac"),
+            PlaylistLocation::File(PathBuf::from("/music/Café/B.flac"))
+        );
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept_as_written() {
+        assert_eq!(
+            resolved("file:///music/100%25%zz%+1%4.mp3"),
+            PlaylistLocation::File(PathBuf::from("/music/100%%zz%+1%4.mp3"))
+        );
+    }
+
+    #[test]
+    fn plain_paths_are_left_alone() {
+        assert_eq!(resolved("My%20Song.mp3"), PlaylistLocation::File(PathBuf::from("/playlists/My%20Song.mp3")));
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+// Renders a position or length as MM:SS, or H:MM:SS once it reaches an hour
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// Like format_duration with milliseconds added, for lining up precise seeks
+pub fn format_duration_precise(duration: Duration) -> String {
+    format!("{}.{:03}", format_duration(duration), duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_times_read_as_minutes_and_seconds() {
+        assert_eq!(format_duration(Duration::ZERO), "00:00");
+        assert_eq!(format_duration(Duration::from_secs(59)), "00:59");
+        assert_eq!(format_duration(Duration::from_secs(61)), "01:01");
+    }
+
+    #[test]
+    fn hours_are_shown_once_reached() {
+        assert_eq!(format_duration(Duration::from_secs(3599)), "59:59");
+        assert_eq!(format_duration(Duration::from_secs(3661)), "1:01:01");
+    }
+
+    #[test]
+    fn precise_times_add_milliseconds() {
+        assert_eq!(format_duration_precise(Duration::from_millis(61_042)), "01:01.042");
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Moves a file to the user's trash so it can still be restored from the file manager.
+// Implements the freedesktop.org trash layout; other platforms report it as unsupported.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Moving to trash isn't supported on this platform"));
+    }
+
+    let path = path.canonicalize()?;
+    let trash_dir = trash_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No trash folder found"))?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let file_name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))?;
+    let (trashed_name, info_path) = unique_name(&files_dir, &info_dir, &file_name.to_string_lossy());
+
+    // The info file is written first so a crash never leaves an entry that can't be restored
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path.to_string_lossy()),
+        deletion_date(),
+    );
+    fs::write(&info_path, info)?;
+
+    let destination = files_dir.join(&trashed_name);
+    let moved = fs::rename(&path, &destination).or_else(|_| {
+        // Different filesystem: fall back to copying and removing the original
+        fs::copy(&path, &destination)?;
+        fs::remove_file(&path)
+    });
+    if moved.is_err() {
+        let _ = fs::remove_file(&info_path);
+    }
+    moved
+}
+
+fn trash_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("Trash"))
+}
+
+// Picks a name not already used in the trash, adding a counter before the extension if needed
+fn unique_name(files_dir: &Path, info_dir: &Path, file_name: &str) -> (String, PathBuf) {
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (file_name, String::new()),
+    };
+
+    let mut counter = 1;
+    loop {
+        let name = if counter == 1 {
+            file_name.to_string()
+        } else {
+            format!("{} {}{}", stem, counter, extension)
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", name));
+        if !files_dir.join(&name).exists() && !info_path.exists() {
+            return (name, info_path);
+        }
+        counter += 1;
+    }
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Current time as YYYY-MM-DDThh:mm:ss. This is UTC since std has no time zone support.
+fn deletion_date() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}